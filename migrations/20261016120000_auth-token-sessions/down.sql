@@ -0,0 +1,7 @@
+begin;
+
+alter table pin.auth_tokens
+    drop column last_seen,
+    drop column user_agent;
+
+commit;
@@ -0,0 +1,7 @@
+begin;
+
+alter table pin.auth_tokens
+    add column last_seen  timestamptz,
+    add column user_agent text;
+
+commit;
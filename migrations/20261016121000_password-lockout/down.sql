@@ -0,0 +1,9 @@
+begin;
+
+drop index pin.idx_passwords_unique_user;
+
+alter table pin.passwords
+    drop column failed_attempts,
+    drop column locked_until;
+
+commit;
@@ -0,0 +1,10 @@
+begin;
+
+alter table pin.passwords
+    add column failed_attempts int not null default 0,
+    add column locked_until    timestamptz;
+
+create unique index idx_passwords_unique_user on pin.passwords (user_id)
+    where deleted is false;
+
+commit;
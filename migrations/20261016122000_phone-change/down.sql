@@ -0,0 +1,18 @@
+begin;
+
+alter table pin.verification_codes
+    drop column phone_id;
+
+update pin.phones
+    set deleted = true, modified = now()
+    where pending is true and deleted is false;
+
+drop index pin.idx_phones_user_pending;
+drop index pin.idx_phones_user;
+create unique index idx_phones_user on pin.phones (user_id)
+    where deleted is false;
+
+alter table pin.phones
+    drop column pending;
+
+commit;
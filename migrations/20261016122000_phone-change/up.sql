@@ -0,0 +1,18 @@
+begin;
+
+-- a pending phone is a replacement number that's waiting on verification,
+-- each user can have one active and one pending phone
+alter table pin.phones
+    add column pending boolean not null default false;
+
+drop index pin.idx_phones_user;
+create unique index idx_phones_user on pin.phones (user_id)
+    where deleted is false and pending is false;
+create unique index idx_phones_user_pending on pin.phones (user_id)
+    where deleted is false and pending is true;
+
+-- codes sent to a pending phone are only valid for confirming that phone
+alter table pin.verification_codes
+    add column phone_id bigint references pin.phones (id);
+
+commit;
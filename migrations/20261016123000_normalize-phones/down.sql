@@ -0,0 +1,6 @@
+begin;
+
+-- the E.164 backfill happens outside of migrations and can't be reversed,
+-- normalized numbers are still valid for the old code, so this is a no-op
+
+commit;
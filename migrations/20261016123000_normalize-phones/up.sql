@@ -0,0 +1,7 @@
+begin;
+
+-- existing numbers are normalized to E.164 by the server on startup (or with
+-- `pinion phones normalize`) so they're parsed with the same rules and
+-- DEFAULT_PHONE_REGION as every lookup, this is a no-op
+
+commit;
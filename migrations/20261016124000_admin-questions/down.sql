@@ -0,0 +1,10 @@
+begin;
+
+drop index pin.idx_questions_pinned_date;
+alter table pin.questions
+    drop column pinned_date;
+
+alter table pin.users
+    drop column admin;
+
+commit;
@@ -0,0 +1,13 @@
+begin;
+
+alter table pin.users
+    add column admin boolean not null default false;
+
+-- a question pinned to a date is used as the question of the day on that date
+-- instead of whatever is next by priority
+alter table pin.questions
+    add column pinned_date date;
+create unique index idx_questions_pinned_date on pin.questions (pinned_date)
+    where deleted is false and pinned_date is not null;
+
+commit;
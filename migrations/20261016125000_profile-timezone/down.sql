@@ -0,0 +1,6 @@
+begin;
+
+alter table pin.profiles
+    drop column timezone;
+
+commit;
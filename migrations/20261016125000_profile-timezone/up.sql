@@ -0,0 +1,7 @@
+begin;
+
+-- iana zone name (see pg_timezone_names) used to decide when a user's day rolls over
+alter table pin.profiles
+    add column timezone text;
+
+commit;
@@ -0,0 +1,18 @@
+begin;
+
+alter table pin.questions
+    add column pinned_date date;
+create unique index idx_questions_pinned_date on pin.questions (pinned_date)
+    where deleted is false and pinned_date is not null;
+
+update pin.questions q
+    set pinned_date = s.day
+    from pin.question_schedule s
+    where s.question_id = q.id
+        and s.deleted is false
+        and s.auto is false
+        and q.used is null;
+
+drop table pin.question_schedule;
+
+commit;
@@ -0,0 +1,41 @@
+begin;
+
+-- the question of the day for a date is the question scheduled for the latest day
+-- on or before it. `auto` rows are filled in by priority and may be reassigned
+-- until their day arrives, rows added by an admin are left alone
+create table pin.question_schedule
+(
+    id          bigint primary key   default pin.id_gen(),
+    day         date        not null,
+    question_id bigint      not null references pin.questions (id),
+    auto        boolean     not null default true,
+    deleted     boolean     not null default false,
+    created     timestamptz not null default now(),
+    modified    timestamptz not null default now()
+);
+create unique index idx_question_schedule_day on pin.question_schedule (day)
+    where deleted is false;
+create index idx_question_schedule_question on pin.question_schedule (question_id);
+
+-- days that already had a question of the day
+insert into pin.question_schedule (day, question_id, auto)
+select distinct on (timezone('America/New_York', used)::date)
+    timezone('America/New_York', used)::date, id, false
+from pin.questions
+where used is not null and deleted is false
+order by timezone('America/New_York', used)::date, used;
+
+-- questions that were pinned to an upcoming date
+insert into pin.question_schedule (day, question_id, auto)
+select pinned_date, id, false
+from pin.questions
+where pinned_date is not null
+    and used is null
+    and deleted is false
+on conflict do nothing;
+
+drop index pin.idx_questions_pinned_date;
+alter table pin.questions
+    drop column pinned_date;
+
+commit;
@@ -0,0 +1,6 @@
+begin;
+
+drop trigger pinions_notify_change on pin.pinions;
+drop function pin.notify_pinion_change();
+
+commit;
@@ -0,0 +1,27 @@
+begin;
+
+-- notify listeners on the `pinion_changes` channel with the question and
+-- options whose counts changed, so tallies can be refreshed as answers come in
+create function pin.notify_pinion_change() returns trigger as $$
+begin
+    if tg_op in ('UPDATE', 'DELETE') then
+        perform pg_notify('pinion_changes', json_build_object(
+            'question_id', old.question_id,
+            'multi_selection', old.multi_selection
+        )::text);
+    end if;
+    if tg_op in ('INSERT', 'UPDATE') then
+        perform pg_notify('pinion_changes', json_build_object(
+            'question_id', new.question_id,
+            'multi_selection', new.multi_selection
+        )::text);
+    end if;
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger pinions_notify_change
+    after insert or update or delete on pin.pinions
+    for each row execute function pin.notify_pinion_change();
+
+commit;
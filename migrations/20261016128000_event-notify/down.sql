@@ -0,0 +1,8 @@
+begin;
+
+drop trigger friends_notify_requested on pin.friends;
+drop function pin.notify_friend_requested();
+drop trigger comments_notify_added on pin.comments;
+drop function pin.notify_comment_added();
+
+commit;
@@ -0,0 +1,34 @@
+begin;
+
+-- live updates for subscriptions, see `pinion_changes` for answers
+create function pin.notify_comment_added() returns trigger as $$
+begin
+    perform pg_notify('comment_added', json_build_object(
+        'id', new.id,
+        'pinion_id', new.pinion_id
+    )::text);
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger comments_notify_added
+    after insert on pin.comments
+    for each row execute function pin.notify_comment_added();
+
+create function pin.notify_friend_requested() returns trigger as $$
+begin
+    if new.accepted is null and new.deleted is false then
+        perform pg_notify('friend_requested', json_build_object(
+            'id', new.id,
+            'acceptor_id', new.acceptor_id
+        )::text);
+    end if;
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger friends_notify_requested
+    after insert on pin.friends
+    for each row execute function pin.notify_friend_requested();
+
+commit;
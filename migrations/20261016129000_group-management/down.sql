@@ -0,0 +1,9 @@
+begin;
+
+drop table pin.group_invites;
+
+alter table pin.group_roles
+    drop column can_invite,
+    drop column can_manage;
+
+commit;
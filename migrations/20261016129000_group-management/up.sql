@@ -0,0 +1,30 @@
+begin;
+
+-- what each group role is allowed to do. Checked by the group mutations
+-- instead of comparing role names
+alter table pin.group_roles
+    add column can_invite boolean not null default false,
+    add column can_manage boolean not null default false;
+update pin.group_roles
+    set can_invite = true, can_manage = true
+    where role = 'admin';
+
+-- a member with `can_invite` inviting another user. The invitee joins the
+-- group by accepting it
+create table pin.group_invites
+(
+    id         bigint primary key   default pin.id_gen(),
+    group_id   bigint      not null references pin.groups (id),
+    inviter_id bigint      not null references pin.users (id),
+    invitee_id bigint      not null references pin.users (id),
+    accepted   timestamptz,
+    deleted    boolean     not null default false,
+    created    timestamptz not null default now(),
+    modified   timestamptz not null default now()
+);
+create unique index idx_group_invites_group_invitee on pin.group_invites (group_id, invitee_id)
+    where deleted is false and accepted is null;
+create index idx_group_invites_invitee on pin.group_invites (invitee_id)
+    where deleted is false and accepted is null;
+
+commit;
@@ -0,0 +1,19 @@
+begin;
+
+delete from pin.question_schedule
+    where group_id is not null;
+drop index pin.idx_question_schedule_group_day;
+drop index pin.idx_question_schedule_day;
+alter table pin.question_schedule
+    drop column group_id;
+create unique index idx_question_schedule_day on pin.question_schedule (day)
+    where deleted is false;
+
+-- group questions can't become global ones
+update pin.questions
+    set deleted = true, modified = now()
+    where group_id is not null;
+alter table pin.questions
+    drop column group_id;
+
+commit;
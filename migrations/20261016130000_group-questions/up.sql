@@ -0,0 +1,18 @@
+begin;
+
+-- questions submitted by group admins, only shown to members of the group.
+-- Each group's questions are scheduled on their own track of the calendar
+alter table pin.questions
+    add column group_id bigint references pin.groups (id);
+create index idx_questions_group on pin.questions (group_id)
+    where deleted is false and group_id is not null;
+
+alter table pin.question_schedule
+    add column group_id bigint references pin.groups (id);
+drop index pin.idx_question_schedule_day;
+create unique index idx_question_schedule_day on pin.question_schedule (day)
+    where deleted is false and group_id is null;
+create unique index idx_question_schedule_group_day on pin.question_schedule (group_id, day)
+    where deleted is false and group_id is not null;
+
+commit;
@@ -0,0 +1,6 @@
+begin;
+
+drop function pin.is_blocked(bigint, bigint);
+drop table pin.blocks;
+
+commit;
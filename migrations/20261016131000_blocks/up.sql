@@ -0,0 +1,32 @@
+begin;
+
+-- a block hides the two users from each other in both directions
+create table pin.blocks
+(
+    id         bigint primary key   default pin.id_gen(),
+    blocker_id bigint      not null references pin.users (id),
+    blocked_id bigint      not null references pin.users (id),
+    deleted    boolean     not null default false,
+    created    timestamptz not null default now(),
+    modified   timestamptz not null default now(),
+    constraint not_same_user check (blocker_id != blocked_id)
+);
+create unique index idx_blocks_unique on pin.blocks (blocker_id, blocked_id)
+    where deleted is false;
+create index idx_blocks_blocked on pin.blocks (blocked_id)
+    where deleted is false;
+
+-- every query that hides blocked users goes through this, so both
+-- directions are always checked the same way
+create function pin.is_blocked(a bigint, b bigint) returns boolean as $$
+    select exists(
+        select 1 from pin.blocks
+        where deleted is false
+            and (
+                (blocker_id = a and blocked_id = b)
+                or (blocker_id = b and blocked_id = a)
+            )
+    );
+$$ language sql stable;
+
+commit;
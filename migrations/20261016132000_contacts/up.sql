@@ -0,0 +1,20 @@
+begin;
+
+-- signed up users found in a user's uploaded contacts (via checkPhones).
+-- only matches are kept, numbers that don't belong to a user are never stored
+create table pin.contacts
+(
+    id              bigint primary key   default pin.id_gen(),
+    user_id         bigint      not null references pin.users (id),
+    contact_user_id bigint      not null references pin.users (id),
+    deleted         boolean     not null default false,
+    created         timestamptz not null default now(),
+    modified        timestamptz not null default now(),
+    constraint not_same_user check (user_id != contact_user_id)
+);
+create unique index idx_contacts_unique on pin.contacts (user_id, contact_user_id)
+    where deleted is false;
+create index idx_contacts_contact_user on pin.contacts (contact_user_id)
+    where deleted is false;
+
+commit;
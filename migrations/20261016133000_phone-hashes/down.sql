@@ -0,0 +1,5 @@
+begin;
+
+alter table pin.phones drop column number_hmac;
+
+commit;
@@ -0,0 +1,9 @@
+begin;
+
+-- hmac of the e.164 number with CONTACT_HASH_KEY, for matching hashed contacts.
+-- existing rows are filled in by the server on startup
+alter table pin.phones add column number_hmac text;
+create index idx_phones_number_hmac on pin.phones (number_hmac)
+    where deleted is false and pending is false;
+
+commit;
@@ -0,0 +1,26 @@
+begin;
+
+-- invites texted to contacts who haven't signed up. the inviter and invitee become
+-- friends when the invited number (or a user who followed the invite link) is verified
+create table pin.invites
+(
+    id         bigint primary key   default pin.id_gen(),
+    inviter_id bigint      not null references pin.users (id),
+    number     text        not null,
+    -- set when someone signs up with the invite's code
+    invitee_id bigint references pin.users (id),
+    -- null when the number was texted recently by another invite
+    sent       timestamptz,
+    accepted   timestamptz,
+    deleted    boolean     not null default false,
+    created    timestamptz not null default now(),
+    modified   timestamptz not null default now()
+);
+create unique index idx_invites_inviter_number on pin.invites (inviter_id, number)
+    where deleted is false;
+create index idx_invites_number on pin.invites (number)
+    where deleted is false and accepted is null;
+create index idx_invites_invitee on pin.invites (invitee_id)
+    where deleted is false and accepted is null;
+
+commit;
@@ -0,0 +1,33 @@
+begin;
+
+-- responses to and questions of the new kinds can't be represented anymore
+delete from pin.comments
+where pinion_id in (select id from pin.pinions where multi_selection is null);
+delete from pin.pinions
+where multi_selection is null;
+delete from pin.question_schedule
+where question_id in (select id from pin.questions where kind != 'multi');
+delete from pin.question_multi_option_tallies
+where question_id in (select id from pin.questions where kind != 'multi');
+delete from pin.question_multi_options
+where question_id in (select id from pin.questions where kind != 'multi');
+delete from pin.questions
+where kind != 'multi';
+
+alter table pin.pinions
+    drop constraint one_answer,
+    drop column ranking,
+    drop column text_value,
+    drop column scale_value,
+    alter column multi_selection set not null;
+
+alter table pin.questions
+    drop constraint scale_range,
+    drop column scale_step,
+    drop column scale_max,
+    drop column scale_min;
+
+delete from pin.question_kind
+where kind in ('scale', 'text', 'ranking');
+
+commit;
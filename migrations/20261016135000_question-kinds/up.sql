@@ -0,0 +1,29 @@
+begin;
+
+-- scale questions are answered with a number from scale_min to scale_max in steps of
+-- scale_step, text questions with free text, and ranking questions by ordering all
+-- of the question's options (stored in pin.question_multi_options like multi's)
+insert into pin.question_kind (kind)
+values ('scale'),
+       ('text'),
+       ('ranking');
+
+alter table pin.questions
+    add column scale_min  bigint,
+    add column scale_max  bigint,
+    add column scale_step bigint,
+    add constraint scale_range check (
+        (kind = 'scale') = (scale_min is not null and scale_max is not null and scale_step is not null)
+        and (scale_step is null or (scale_step > 0 and scale_min < scale_max))
+    );
+
+-- each pinion stores its answer in the column for its question's kind,
+-- rankings are option ids from most to least preferred
+alter table pin.pinions
+    alter column multi_selection drop not null,
+    add column scale_value bigint,
+    add column text_value  text,
+    add column ranking     bigint[],
+    add constraint one_answer check (num_nonnulls(multi_selection, scale_value, text_value, ranking) = 1);
+
+commit;
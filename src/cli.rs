@@ -0,0 +1,327 @@
+/*!
+Command line subcommands
+
+The binary starts the server when run without arguments, otherwise:
+
+```text
+pinion questions import <file.json|file.csv> [--format json|csv] [--dry-run]
+pinion questions export [--format json|csv] [--unused]
+pinion tallies backfill
+pinion phones rehash
+pinion phones normalize
+```
+*/
+use crate::error::LogError;
+use crate::models::{NewQuestion, Question};
+use crate::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::io::Write;
+
+const USAGE: &str = "\
+usage:
+    pinion                      start the server
+    pinion questions import <file.json|file.csv> [--format json|csv] [--dry-run]
+    pinion questions export [--format json|csv] [--unused]
+    pinion tallies backfill     recount the tallies of every used or answered question
+    pinion phones rehash        recompute phone number hashes after changing CONTACT_HASH_KEY
+    pinion phones normalize     rewrite numbers that aren't E.164 yet, also done on startup
+
+json files contain a list of {\"prompt\": \"..\", \"options\": [\"..\"], \"priority\": 1}
+objects (priority is optional). csv files have a header row with a `prompt` column,
+an optional `priority` column, and every other column is treated as an option.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn parse(s: &str) -> Result<Format> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(AppError::from(format!("unknown format: {s}\n\n{USAGE}"))),
+        }
+    }
+
+    fn from_path(path: &str) -> Option<Format> {
+        let ext = std::path::Path::new(path).extension()?.to_str()?;
+        Format::parse(&ext.to_lowercase()).ok()
+    }
+}
+
+/// A question as it appears in import and export files
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct QuestionRecord {
+    #[serde(flatten)]
+    question: NewQuestion,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<i64>,
+}
+
+/// Run the subcommand given by `args` (not including the binary name)
+pub async fn run(pool: &PgPool, args: &[String]) -> Result<()> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        ["questions", "import", rest @ ..] => {
+            let mut path = None;
+            let mut format = None;
+            let mut dry_run = false;
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                match *arg {
+                    "--format" => format = Some(Format::parse(rest.next().unwrap_or(&""))?),
+                    "--dry-run" => dry_run = true,
+                    p if path.is_none() && !p.starts_with("--") => path = Some(p),
+                    other => {
+                        return Err(AppError::from(format!(
+                            "unexpected argument: {other}\n\n{USAGE}"
+                        )))
+                    }
+                }
+            }
+            let path =
+                path.ok_or_else(|| AppError::from(format!("missing import file\n\n{USAGE}")))?;
+            let format = format.or_else(|| Format::from_path(path)).ok_or_else(|| {
+                AppError::from(format!("can't tell the format of {path}, pass --format"))
+            })?;
+            import_questions(pool, path, format, dry_run).await
+        }
+        ["questions", "export", rest @ ..] => {
+            let mut format = Format::Json;
+            let mut unused_only = false;
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                match *arg {
+                    "--format" => format = Format::parse(rest.next().unwrap_or(&""))?,
+                    "--unused" => unused_only = true,
+                    other => {
+                        return Err(AppError::from(format!(
+                            "unexpected argument: {other}\n\n{USAGE}"
+                        )))
+                    }
+                }
+            }
+            export_questions(pool, format, unused_only).await
+        }
+        ["tallies", "backfill"] => {
+            let count = crate::tally::backfill(pool).await?;
+            println!("recounted tallies for {count} questions");
+            Ok(())
+        }
+        ["phones", "rehash"] => {
+            let count = crate::phone::backfill_hashes(pool, true).await?;
+            println!("rehashed {count} phone numbers");
+            Ok(())
+        }
+        ["phones", "normalize"] => {
+            let report = crate::phone::normalize_existing(pool).await?;
+            println!("normalized {} phone numbers", report.normalized);
+            for p in &report.unparseable {
+                println!(
+                    "can't normalize phone {} (user {}): {}",
+                    p.id, p.user_id, p.number
+                );
+            }
+            for p in &report.removed {
+                println!(
+                    "deleted phone {} (user {}): {}, a more recently verified phone has the same number",
+                    p.id, p.user_id, p.number
+                );
+            }
+            Ok(())
+        }
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(AppError::from(format!(
+            "unknown command: {}\n\n{USAGE}",
+            args.join(" ")
+        ))),
+    }
+}
+
+fn read_records(path: &str, format: Format) -> Result<Vec<QuestionRecord>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| AppError::from(format!("error reading {path}: {e}")))?;
+    match format {
+        Format::Json => Ok(serde_json::from_str(&content)?),
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .from_reader(content.as_bytes());
+            let headers = reader
+                .headers()
+                .map_err(|e| AppError::from(format!("error reading csv header: {e}")))?
+                .clone();
+            let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+            let prompt_col = column("prompt")
+                .ok_or_else(|| AppError::from("csv header is missing a `prompt` column"))?;
+            let priority_col = column("priority");
+
+            let mut records = vec![];
+            for (i, row) in reader.records().enumerate() {
+                // header is line 1
+                let line = i + 2;
+                let row = row.map_err(|e| AppError::from(format!("line {line}: {e}")))?;
+                let priority = match priority_col.and_then(|c| row.get(c)) {
+                    None | Some("") => None,
+                    Some(p) => Some(p.parse::<i64>().map_err(|_| {
+                        AppError::from(format!("line {line}: invalid priority: {p}"))
+                    })?),
+                };
+                let options = row
+                    .iter()
+                    .enumerate()
+                    .filter(|(c, v)| *c != prompt_col && Some(*c) != priority_col && !v.is_empty())
+                    .map(|(_, v)| v.to_string())
+                    .collect();
+                records.push(QuestionRecord {
+                    question: NewQuestion::multi(
+                        row.get(prompt_col).unwrap_or_default().to_string(),
+                        options,
+                    ),
+                    priority,
+                });
+            }
+            Ok(records)
+        }
+    }
+}
+
+async fn import_questions(pool: &PgPool, path: &str, format: Format, dry_run: bool) -> Result<()> {
+    let records = read_records(path, format)?;
+
+    // validate everything up front so a bad row doesn't leave a partial import
+    let mut errors = vec![];
+    let mut valid = vec![];
+    for (i, record) in records.into_iter().enumerate() {
+        match record.question.validate() {
+            Ok(question) => valid.push((question, record.priority)),
+            Err(e) => errors.push(format!("question {}: {}", i + 1, describe(&e))),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(AppError::from(format!(
+            "{path} has invalid questions, nothing was imported:\n  {}",
+            errors.join("\n  ")
+        )));
+    }
+
+    let mut tr = pool
+        .begin()
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error starting transaction")?;
+    let existing: Vec<(String,)> = sqlx::query_as(
+        r##"select lower(prompt) from pin.questions where deleted is false and group_id is null"##,
+    )
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error loading existing prompts")?;
+    let mut seen = existing.into_iter().map(|(p,)| p).collect::<HashSet<_>>();
+
+    let mut imported = 0;
+    let mut skipped = 0;
+    for (question, priority) in &valid {
+        if !seen.insert(question.prompt.to_lowercase()) {
+            println!("skipping duplicate: {}", question.prompt);
+            skipped += 1;
+            continue;
+        }
+        Question::create(&mut tr, question, *priority, None)
+            .await
+            .log_error_msg(|| format!("error importing question: {}", question.prompt))?;
+        imported += 1;
+    }
+
+    if dry_run {
+        tr.rollback().await.map_err(AppError::from)?;
+        println!("dry run: would import {imported} questions, skipping {skipped} duplicates");
+    } else {
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error committing import")?;
+        println!("imported {imported} questions, skipped {skipped} duplicates");
+    }
+    Ok(())
+}
+
+async fn export_questions(pool: &PgPool, format: Format, unused_only: bool) -> Result<()> {
+    let rows: Vec<(String, i64, Vec<String>)> = sqlx::query_as(
+        r##"
+        select q.prompt, q.priority,
+            array(
+                select o.value from pin.question_multi_options o
+                where o.question_id = q.id and o.deleted is false
+                order by o.rank
+            ) as options
+        from pin.questions q
+        where q.deleted is false
+            and q.group_id is null
+            and q.kind = 'multi'
+            and ($1 is false or q.used is null)
+        order by q.used asc nulls last, q.priority asc, q.created asc
+        "##,
+    )
+    .bind(unused_only)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error loading questions to export")?;
+    let records = rows
+        .into_iter()
+        .map(|(prompt, priority, options)| QuestionRecord {
+            question: NewQuestion::multi(prompt, options),
+            priority: Some(priority),
+        })
+        .collect::<Vec<_>>();
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &records)?;
+            writeln!(out).map_err(|e| AppError::from(format!("error writing export: {e}")))?;
+        }
+        Format::Csv => {
+            let width = records
+                .iter()
+                .map(|r| r.question.options.len())
+                .max()
+                .unwrap_or(2);
+            let mut writer = csv::Writer::from_writer(out);
+            let mut header = vec!["prompt".to_string(), "priority".to_string()];
+            header.extend((1..=width).map(|i| format!("option{i}")));
+            let csv_err = |e: csv::Error| AppError::from(format!("error writing export: {e}"));
+            writer.write_record(&header).map_err(csv_err)?;
+            for r in records {
+                let mut row = vec![
+                    r.question.prompt,
+                    r.priority.unwrap_or_default().to_string(),
+                ];
+                row.extend(r.question.options);
+                row.resize(2 + width, String::new());
+                writer.write_record(&row).map_err(csv_err)?;
+            }
+            writer
+                .flush()
+                .map_err(|e| AppError::from(format!("error writing export: {e}")))?;
+        }
+    }
+    Ok(())
+}
+
+fn describe(e: &AppError) -> String {
+    match e {
+        AppError::BadRequest(s) | AppError::E(s) => s.clone(),
+        e => format!("{e:?}"),
+    }
+}
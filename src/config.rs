@@ -1,3 +1,4 @@
+use crate::schedule::ScheduleMode;
 use std::env;
 use std::io::Read;
 fn env_or(k: &str, default: &str) -> String {
@@ -29,6 +30,21 @@ pub struct Config {
     pub twilio_secret: String,
     pub default_phone_number: String,
     pub allowed_phone_numbers: Option<Vec<String>>,
+    // region used for phone numbers entered without a country code
+    pub default_phone_region: String,
+
+    // sms delivery backend: twilio, file, or http
+    pub sms_backend: String,
+    // where the file backend appends messages
+    pub sms_file_path: String,
+    // endpoint and optional authorization header for the http backend
+    pub sms_http_url: Option<String>,
+    pub sms_http_authorization: Option<String>,
+
+    // link texted by inviteContact, the invite code is added as a `code` query param
+    pub invite_url: String,
+    // how many contacts a user can invite a day
+    pub invite_daily_limit: i64,
 
     // db config
     pub database_url: String,
@@ -40,8 +56,33 @@ pub struct Config {
     // key used for signing/hashing things
     pub signing_key: String,
 
+    // key clients hmac phone numbers with for hashed contact discovery,
+    // it's handed out to clients so it must not be the signing key
+    pub contact_hash_key: String,
+
     // auth cookie expiration
     pub auth_expiration_seconds: u32,
+
+    // password requirements and lockout after repeated failed logins
+    pub password_min_length: usize,
+    pub password_max_failed_attempts: i32,
+    pub password_lockout_seconds: u32,
+
+    // which days get a new question of the day: daily, weekdays, or business_days
+    pub question_schedule: ScheduleMode,
+    // how many days ahead the question schedule is filled in
+    pub question_schedule_lookahead_days: i64,
+
+    // how often tallies of recently answered questions are recounted as a
+    // safety net for missed notifications, never recounted periodically when unset
+    pub tally_reconcile_seconds: Option<i64>,
+
+    // how long after first answering a question the answer can be changed,
+    // answers never lock when unset
+    pub pinion_lock_seconds: Option<i64>,
+    // how many past days' questions can still be answered, 0 for only today's
+    pub answer_window_days: i64,
+
     // phone challenge expiration, applies to phone challenge cookie
     // and verification token lifetime
     pub challenge_phone_expiration_seconds: u32,
@@ -80,6 +121,15 @@ impl Config {
             twilio_secret: env_or("TWILIO_SECRET", "X"),
             default_phone_number: env_or("DEFAULT_PHONE_NUMBER", "0"),
             allowed_phone_numbers,
+            default_phone_region: env_or("DEFAULT_PHONE_REGION", "US"),
+            sms_backend: env_or("SMS_BACKEND", "twilio"),
+            sms_file_path: env_or("SMS_FILE_PATH", "sms.log"),
+            sms_http_url: std::env::var("SMS_HTTP_URL").ok(),
+            sms_http_authorization: std::env::var("SMS_HTTP_AUTHORIZATION").ok(),
+            invite_url: env_or("INVITE_URL", "https://getpinion.com/invite"),
+            invite_daily_limit: env_or("INVITE_DAILY_LIMIT", "10")
+                .parse()
+                .expect("invalid INVITE_DAILY_LIMIT"),
             database_url: env_or("DATABASE_URL", "error"),
             db_max_connections: env_or("DATABASE_MAX_CONNECTIONS", "5")
                 .parse()
@@ -88,12 +138,41 @@ impl Config {
             auth_expiration_seconds: env_or("AUTH_EXPIRATION_SECONDS", "15552000")
                 .parse()
                 .expect("invalid auth_expiration_seconds"),
+            password_min_length: env_or("PASSWORD_MIN_LENGTH", "8")
+                .parse()
+                .expect("invalid password_min_length"),
+            password_max_failed_attempts: env_or("PASSWORD_MAX_FAILED_ATTEMPTS", "5")
+                .parse()
+                .expect("invalid password_max_failed_attempts"),
+            // 60 * 15
+            password_lockout_seconds: env_or("PASSWORD_LOCKOUT_SECONDS", "900")
+                .parse()
+                .expect("invalid password_lockout_seconds"),
+            question_schedule: env_or("QOD_SCHEDULE", "daily")
+                .parse()
+                .expect("invalid QOD_SCHEDULE"),
+            question_schedule_lookahead_days: env_or("QOD_SCHEDULE_LOOKAHEAD_DAYS", "7")
+                .parse()
+                .expect("invalid QOD_SCHEDULE_LOOKAHEAD_DAYS"),
+            tally_reconcile_seconds: std::env::var("TALLY_RECONCILE_SECONDS").ok().map(|s| {
+                s.parse::<i64>()
+                    .expect("invalid TALLY_RECONCILE_SECONDS")
+                    .max(1)
+            }),
+            pinion_lock_seconds: std::env::var("PINION_LOCK_SECONDS")
+                .ok()
+                .map(|s| s.parse().expect("invalid PINION_LOCK_SECONDS")),
+            answer_window_days: env_or("ANSWER_WINDOW_DAYS", "0")
+                .parse::<i64>()
+                .expect("invalid ANSWER_WINDOW_DAYS")
+                .max(0),
             // 60 * 2
             challenge_phone_expiration_seconds: env_or("CHALLENGE_PHONE_EXPIRATION_SECONDS", "120")
                 .parse()
                 .expect("invalid challenge_phone_expiration_seconds"),
             encryption_key: env_or("ENCRYPTION_KEY", "01234567890123456789012345678901"),
             signing_key: env_or("SIGNING_KEY", "01234567890123456789012345678901"),
+            contact_hash_key: env_or("CONTACT_HASH_KEY", "98765432109876543210987654321098"),
         }
     }
     pub fn initialize(&self) {
@@ -21,7 +21,6 @@ pub enum AppError {
     #[error("unverified")]
     Unverified(String),
 
-    #[allow(unused)]
     #[error("forbidden")]
     Forbidden(String),
 
@@ -31,6 +30,9 @@ pub enum AppError {
     #[error("invalid verification code")]
     InvalidVerificationCode(String),
 
+    #[error("invalid phone number")]
+    InvalidPhoneNumber(String),
+
     #[error("hex error")]
     Hex(#[from] hex::FromHexError),
 
@@ -91,6 +93,7 @@ impl AppError {
             AppError::InvalidVerificationCode(s) => {
                 tracing::warn!("invalid verification code: {}", s)
             }
+            AppError::InvalidPhoneNumber(s) => tracing::warn!("{}", s),
             AppError::E(s) => tracing::error!("Error: {}", s),
             e => tracing::error!("Error: {:?}", e),
         }
@@ -98,6 +101,7 @@ impl AppError {
     fn log_error_msg<S: AsRef<str>>(&self, msg: S) {
         match self {
             AppError::InvalidVerificationCode(s) => tracing::warn!("{}: {}", msg.as_ref(), s),
+            AppError::InvalidPhoneNumber(s) => tracing::warn!("{}: {}", msg.as_ref(), s),
             AppError::E(s) => tracing::error!("{}: {}", msg.as_ref(), s),
             e => tracing::error!("{}: {:?}", msg.as_ref(), e),
         }
@@ -149,6 +153,11 @@ impl ErrorExtensions for AppError {
                     e.set("error", s.clone());
                     e.set("key", "INVALID_CODE");
                 }
+                AppError::InvalidPhoneNumber(s) => {
+                    e.set("code", 400);
+                    e.set("error", s.clone());
+                    e.set("key", "INVALID_PHONE");
+                }
                 AppError::Hex(_) => e.set("code", 500),
                 AppError::Reqwest(_) => e.set("code", 500),
                 AppError::Json(_) => e.set("code", 500),
@@ -0,0 +1,141 @@
+/*!
+Live events
+
+Triggers on `pin.pinions`, `pin.comments` and `pin.friends` send postgres notifications.
+A single listener per server updates tallies and fans the events out to graphql
+subscriptions through broadcast channels. Going through the database means every
+server sees every change, not just the one that handled the mutation.
+*/
+use crate::error::LogError;
+use crate::tally::{self, PinionChange};
+use crate::AppError;
+use serde::Deserialize;
+use sqlx::postgres::{PgListener, PgNotification};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const PINION_CHANNEL: &str = "pinion_changes";
+const COMMENT_CHANNEL: &str = "comment_added";
+const FRIEND_CHANNEL: &str = "friend_requested";
+
+// how many unread events a slow subscriber can fall behind before missing some
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommentAdded {
+    pub id: i64,
+    pub pinion_id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FriendRequested {
+    pub id: i64,
+    pub acceptor_id: i64,
+}
+
+/// Senders for each kind of event, shared through the graphql context
+#[derive(Clone)]
+pub struct Events {
+    /// ids of questions whose tallies changed
+    pub summaries: broadcast::Sender<i64>,
+    pub comments: broadcast::Sender<CommentAdded>,
+    pub friend_requests: broadcast::Sender<FriendRequested>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            summaries: broadcast::channel(CHANNEL_CAPACITY).0,
+            comments: broadcast::channel(CHANNEL_CAPACITY).0,
+            friend_requests: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    async fn dispatch(&self, pool: &PgPool, n: &PgNotification) -> crate::Result<()> {
+        match n.channel() {
+            PINION_CHANNEL => {
+                let change: PinionChange = serde_json::from_str(n.payload())?;
+                tally::handle_change(pool, &change).await?;
+                // nobody listening is fine
+                self.summaries.send(change.question_id).ok();
+            }
+            COMMENT_CHANNEL => {
+                self.comments.send(serde_json::from_str(n.payload())?).ok();
+            }
+            FRIEND_CHANNEL => {
+                self.friend_requests
+                    .send(serde_json::from_str(n.payload())?)
+                    .ok();
+            }
+            other => tracing::warn!("events: unexpected channel {}", other),
+        }
+        Ok(())
+    }
+}
+
+/// Listen for database notifications forever, reconnecting (and reconciling
+/// tallies) whenever the listener connection drops
+pub async fn listen(pool: PgPool, events: Events) {
+    // when the listener last dropped, unknown until it's connected once
+    let mut lost: Option<Instant> = None;
+    loop {
+        let listener = async {
+            let mut listener = PgListener::connect_with(&pool).await?;
+            listener
+                .listen_all([PINION_CHANNEL, COMMENT_CHANNEL, FRIEND_CHANNEL])
+                .await?;
+            Ok::<_, sqlx::Error>(listener)
+        }
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "events: error starting listener");
+        let mut listener = match listener {
+            Ok(listener) => listener,
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        tracing::info!("events: listening for notifications");
+
+        loop {
+            // notifications sent while disconnected are lost, recount after
+            // every (re)connect, with a minute to spare for ones in flight
+            let seconds = lost.map_or(tally::RECONCILE_LOOKBACK_SECONDS, |t| {
+                t.elapsed().as_secs() as i64 + 60
+            });
+            tally::reconcile(&pool, seconds)
+                .await
+                .log_error_msg(|| "events: error reconciling tallies")
+                .ok();
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(n)) => {
+                        events
+                            .dispatch(&pool, &n)
+                            .await
+                            .log_error_msg(|| {
+                                format!("events: error handling {} {}", n.channel(), n.payload())
+                            })
+                            .ok();
+                    }
+                    // connection lost, the next try_recv reconnects
+                    Ok(None) => {
+                        tracing::warn!("events: listener connection lost");
+                        lost = Some(Instant::now());
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::error!("events: listener error {:?}", e);
+                        lost = Some(Instant::now());
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,17 @@
 use crate::models::{
-    Comment, Friend, GroupAssociation, Pinion, PinionWithFriendRelation, Profile, Question,
-    QuestionMultiOption, User,
+    Comment, Friend, Group, GroupAssociation, GroupInvite, Pinion, Profile, Question,
+    QuestionMultiOption, QuestionSummary, User,
 };
 use crate::AppError;
 use async_graphql::dataloader::{DataLoader, HashMapCache};
-use sqlx::PgPool;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest,
+};
+use async_graphql::{Request, ServerResult};
+use sqlx::{FromRow, PgPool, Row};
+use std::any::TypeId;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct PgLoader {
     pool: PgPool,
@@ -17,6 +23,50 @@ impl PgLoader {
 }
 pub type AppLoader = DataLoader<PgLoader, HashMapCache>;
 
+/// Gives each operation that doesn't bring its own loader (everything sent over a
+/// websocket) a fresh one. Its cache is off since a subscription can run for hours
+pub struct OperationLoader {
+    pool: PgPool,
+}
+impl OperationLoader {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl ExtensionFactory for OperationLoader {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(OperationLoaderExtension {
+            pool: self.pool.clone(),
+        })
+    }
+}
+
+struct OperationLoaderExtension {
+    pool: PgPool,
+}
+
+#[async_trait::async_trait]
+impl Extension for OperationLoaderExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if !request.data.contains_key(&TypeId::of::<AppLoader>()) {
+            let loader = DataLoader::with_cache(
+                PgLoader::new(self.pool.clone()),
+                tokio::spawn,
+                HashMapCache::default(),
+            );
+            loader.enable_all_cache(false);
+            request.data.insert(loader);
+        }
+        next.run(ctx, request).await
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct UserId(pub i64);
 
@@ -36,6 +86,8 @@ impl async_graphql::dataloader::Loader<UserId> for PgLoader {
             p.verification_attempts as phone_verification_attempts
             from pin.users u
                 inner join pin.phones p on p.user_id = u.id
+                    and p.deleted is false
+                    and p.pending is false
             where u.id in (select * from unnest($1))
         "##;
         let u_ids = keys.iter().map(|c| c.0).collect::<Vec<_>>();
@@ -75,9 +127,19 @@ impl async_graphql::dataloader::Loader<UserForPhone> for PgLoader {
             p.verification_attempts as phone_verification_attempts
             from pin.users u
                 inner join pin.phones p on p.user_id = u.id
+                    and p.deleted is false
+                    and p.pending is false
             where p.number in (select * from unnest($1))
         "##;
-        let numbers = keys.iter().map(|c| c.0.clone()).collect::<Vec<_>>();
+        // keys may be formatted any which way, match them up by their E.164 form
+        let normalized = keys
+            .iter()
+            .filter_map(|k| crate::phone::normalize(&k.0).ok().map(|n| (n, k.clone())))
+            .collect::<Vec<_>>();
+        let numbers = normalized
+            .iter()
+            .map(|(n, _)| n.clone())
+            .collect::<Vec<_>>();
         let res: Vec<User> = sqlx::query_as(query)
             .bind(&numbers)
             .fetch_all(&self.pool)
@@ -87,10 +149,18 @@ impl async_graphql::dataloader::Loader<UserForPhone> for PgLoader {
                 AppError::from(e)
             })?;
         tracing::info!("loaded {} users", res.len());
-        let res = res.into_iter().fold(HashMap::new(), |mut acc, u| {
-            acc.insert(UserForPhone(u.phone_number.clone()), u);
-            acc
-        });
+        let users = res
+            .into_iter()
+            .map(|u| (u.phone_number.clone(), u))
+            .collect::<HashMap<_, _>>();
+        let res = normalized
+            .into_iter()
+            .fold(HashMap::new(), |mut acc, (number, key)| {
+                if let Some(u) = users.get(&number) {
+                    acc.insert(key, u.clone());
+                }
+                acc
+            });
         Ok(res)
     }
 }
@@ -170,6 +240,115 @@ impl async_graphql::dataloader::Loader<GroupAssociationsForUserId> for PgLoader
     }
 }
 
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct GroupId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<GroupId> for PgLoader {
+    type Value = Group;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[GroupId],
+    ) -> std::result::Result<HashMap<GroupId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} groups", keys.len());
+        let query = r##"
+        select * from pin.groups
+            where id in (select * from unnest($1))
+        "##;
+        let keys = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let res: Vec<Group> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} groups", res.len());
+        let res = res
+            .into_iter()
+            .map(|g| (GroupId(g.id), g))
+            .collect::<HashMap<_, _>>();
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct MembersForGroupId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<MembersForGroupId> for PgLoader {
+    type Value = Vec<GroupAssociation>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[MembersForGroupId],
+    ) -> std::result::Result<HashMap<MembersForGroupId, Self::Value>, Self::Error> {
+        tracing::info!("loading members of {} groups", keys.len());
+        let query = r##"
+            select ga.* from pin.group_associations ga
+                inner join pin.users u on ga.user_id = u.id
+            where ga.group_id in (select * from unnest($1))
+                and ga.deleted is false
+                and u.deleted is false
+            order by ga.role = 'admin' desc, ga.sort_rank nulls last, ga.created
+        "##;
+        let keys = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let res: Vec<GroupAssociation> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} group members", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, ga| {
+            acc.entry(MembersForGroupId(ga.group_id))
+                .or_insert_with(Vec::new)
+                .push(ga);
+            acc
+        });
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct GroupInvitesForUserId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<GroupInvitesForUserId> for PgLoader {
+    type Value = Vec<GroupInvite>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[GroupInvitesForUserId],
+    ) -> std::result::Result<HashMap<GroupInvitesForUserId, Self::Value>, Self::Error> {
+        tracing::info!("loading group invites for {} users", keys.len());
+        let query = r##"
+            select gi.* from pin.group_invites gi
+                inner join pin.groups g on gi.group_id = g.id
+            where gi.invitee_id in (select * from unnest($1))
+                and gi.accepted is null
+                and gi.deleted is false
+                and g.deleted is false
+            order by gi.created desc
+        "##;
+        let keys = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let res: Vec<GroupInvite> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} group invites", res.len());
+        let res = res.into_iter().fold(HashMap::new(), |mut acc, gi| {
+            acc.entry(GroupInvitesForUserId(gi.invitee_id))
+                .or_insert_with(Vec::new)
+                .push(gi);
+            acc
+        });
+        Ok(res)
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct FriendsForUserId(pub i64);
 
@@ -185,9 +364,12 @@ impl async_graphql::dataloader::Loader<FriendsForUserId> for PgLoader {
         tracing::info!("loading friends for {} users", keys.len());
         let query = r##"
             select * from pin.friends
-            where requestor_id in (select * from unnest($1))
-                or acceptor_id in (select * from unnest($1))
-                and deleted is false;
+            where (
+                    requestor_id in (select * from unnest($1))
+                    or acceptor_id in (select * from unnest($1))
+                )
+                and deleted is false
+            order by created desc
         "##;
         let keys = keys.iter().map(|u| u.0).collect::<Vec<_>>();
         let res: Vec<Friend> = sqlx::query_as(query)
@@ -231,48 +413,152 @@ impl async_graphql::dataloader::Loader<PinionsOfFriendsForUserQuestionId> for Pg
     {
         tracing::info!("loading pinions of friends for {} users", keys.len());
         let query = r##"
-            select p.*, f.requestor_id, f.acceptor_id
-            from pin.pinions p
+            select k.user_id as viewer_id, p.*
+            from unnest($1::bigint[], $2::bigint[]) as k(user_id, question_id)
                 inner join pin.friends f
-                    on p.user_id = f.requestor_id
-                    or p.user_id = f.acceptor_id
-            where
-                 p.question_id in (select * from unnest($1))
-                 or (f.requestor_id in (select * from unnest($2)) or f.acceptor_id in (select * from unnest ($2)))
-                 and p.deleted is false
-                 and f.deleted is false
+                    on (f.requestor_id = k.user_id or f.acceptor_id = k.user_id)
+                    and f.accepted is not null
+                    and f.deleted is false
+                inner join pin.pinions p
+                    on p.user_id = case
+                        when f.requestor_id = k.user_id then f.acceptor_id
+                        else f.requestor_id
+                    end
+                    and p.question_id = k.question_id
+                    and p.deleted is false
+            where not pin.is_blocked(k.user_id, p.user_id)
+            order by p.created
         "##;
         let u_ids = keys.iter().map(|u| u.0).collect::<Vec<_>>();
         let q_ids = keys.iter().map(|u| u.1).collect::<Vec<_>>();
-        let res: Vec<PinionWithFriendRelation> = sqlx::query_as(query)
-            .bind(&q_ids)
+        let rows = sqlx::query(query)
             .bind(&u_ids)
+            .bind(&q_ids)
             .fetch_all(&self.pool)
             .await
             .map_err(AppError::from)?;
-        tracing::info!("loaded {} friend pinions", res.len());
-        let res = res.into_iter().fold(HashMap::new(), |mut acc, p| {
-            {
-                // Need to push both sides because we don't know who is "asking"
-                // When we the dataloader iterators over the input keys, both "sides"
-                // of the friendship will be available to pull.
-                let e = acc
-                    .entry(PinionsOfFriendsForUserQuestionId(
-                        p.requestor_id,
-                        p.question_id,
-                    ))
-                    .or_insert_with(Vec::new);
-                e.push(p.clone().into());
-                let e = acc
-                    .entry(PinionsOfFriendsForUserQuestionId(
-                        p.acceptor_id,
-                        p.question_id,
-                    ))
-                    .or_insert_with(Vec::new);
-                e.push(p.into());
-            }
-            acc
-        });
+        tracing::info!("loaded {} friend pinions", rows.len());
+        let mut res = HashMap::new();
+        for row in rows {
+            let viewer_id: i64 = row.try_get("viewer_id").map_err(AppError::from)?;
+            let p = Pinion::from_row(&row).map_err(AppError::from)?;
+            res.entry(PinionsOfFriendsForUserQuestionId(viewer_id, p.question_id))
+                .or_insert_with(Vec::new)
+                .push(p);
+        }
+        // every key gets a list, even if no friends answered
+        for k in keys {
+            res.entry(k.clone()).or_insert_with(Vec::new);
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct PinionsOfGroupForQuestionId(pub i64, pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<PinionsOfGroupForQuestionId> for PgLoader {
+    type Value = Vec<Pinion>;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[PinionsOfGroupForQuestionId],
+    ) -> std::result::Result<HashMap<PinionsOfGroupForQuestionId, Self::Value>, Self::Error> {
+        tracing::info!("loading pinions of {} groups", keys.len());
+        let query = r##"
+            select k.group_id, p.*
+            from unnest($1::bigint[], $2::bigint[]) k(group_id, question_id)
+                inner join pin.group_associations ga
+                    on ga.group_id = k.group_id
+                    and ga.deleted is false
+                inner join pin.pinions p
+                    on p.user_id = ga.user_id
+                    and p.question_id = k.question_id
+                    and p.deleted is false
+            order by p.created
+        "##;
+        let g_ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let q_ids = keys.iter().map(|k| k.1).collect::<Vec<_>>();
+        let rows = sqlx::query(query)
+            .bind(&g_ids)
+            .bind(&q_ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        tracing::info!("loaded {} group pinions", rows.len());
+        let mut res = HashMap::new();
+        for row in rows {
+            let group_id: i64 = row.try_get("group_id").map_err(AppError::from)?;
+            let p = Pinion::from_row(&row).map_err(AppError::from)?;
+            res.entry(PinionsOfGroupForQuestionId(group_id, p.question_id))
+                .or_insert_with(Vec::new)
+                .push(p);
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct GroupSummaryForQuestionId(pub i64, pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<GroupSummaryForQuestionId> for PgLoader {
+    type Value = QuestionSummary;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[GroupSummaryForQuestionId],
+    ) -> std::result::Result<HashMap<GroupSummaryForQuestionId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} group summaries", keys.len());
+        // every option gets a row, including ones no member picked
+        let query = r##"
+            select k.group_id, k.question_id, o.id as multi_selection, count(p.id) as count
+            from unnest($1::bigint[], $2::bigint[]) k(group_id, question_id)
+                inner join pin.question_multi_options o
+                    on o.question_id = k.question_id
+                    and o.deleted is false
+                left outer join (
+                    pin.pinions p
+                        inner join pin.group_associations ga
+                            on ga.user_id = p.user_id
+                            and ga.deleted is false
+                ) on p.multi_selection = o.id
+                    and p.deleted is false
+                    and ga.group_id = k.group_id
+            group by k.group_id, k.question_id, o.id, o.rank
+            order by o.rank
+        "##;
+        let g_ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let q_ids = keys.iter().map(|k| k.1).collect::<Vec<_>>();
+        let rows = sqlx::query(query)
+            .bind(&g_ids)
+            .bind(&q_ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(AppError::from)?;
+        let mut counts = HashMap::new();
+        for row in rows {
+            let key = GroupSummaryForQuestionId(
+                row.try_get("group_id").map_err(AppError::from)?,
+                row.try_get("question_id").map_err(AppError::from)?,
+            );
+            let multi_selection: i64 = row.try_get("multi_selection").map_err(AppError::from)?;
+            let count: i64 = row.try_get("count").map_err(AppError::from)?;
+            counts
+                .entry(key)
+                .or_insert_with(Vec::new)
+                .push((multi_selection, count));
+        }
+        let res = keys
+            .iter()
+            .map(|k| {
+                let c = counts.remove(k).unwrap_or_default();
+                (k.clone(), QuestionSummary::from_option_counts(k.1, c))
+            })
+            .collect::<HashMap<_, _>>();
         Ok(res)
     }
 }
@@ -281,14 +567,14 @@ impl async_graphql::dataloader::Loader<PinionsOfFriendsForUserQuestionId> for Pg
 pub struct QuestionOfDay;
 
 pub static QOD_QUERY: &str = r##"
-        select * from pin.questions
+        select q.* from pin.question_schedule s
+            inner join pin.questions q on q.id = s.question_id
             where
-                deleted is false and
-                (
-                    used::date >= timezone('America/New_York', now())::date
-                    or used is null
-                )
-            order by used asc nulls last, priority asc nulls last, created asc
+                s.deleted is false and
+                s.group_id is null and
+                q.deleted is false and
+                s.day <= timezone('America/New_York', now())::date
+            order by s.day desc
             limit 1
         "##;
 
@@ -316,6 +602,132 @@ impl async_graphql::dataloader::Loader<QuestionOfDay> for PgLoader {
     }
 }
 
+/// The question of the day for someone whose day is in the given timezone,
+/// the question scheduled on the latest day on or before their local date.
+/// The schedule is filled ahead so people ahead of the server's day see
+/// tomorrow's question
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct QuestionOfDayForTimezone(pub String);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<QuestionOfDayForTimezone> for PgLoader {
+    type Value = Question;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[QuestionOfDayForTimezone],
+    ) -> std::result::Result<HashMap<QuestionOfDayForTimezone, Self::Value>, Self::Error> {
+        tracing::info!("loading question of the day for {} timezones", keys.len());
+        let query = r##"
+        select distinct on (t.tz) t.tz, q.*
+            from unnest($1::text[]) as t(tz)
+            inner join pin.question_schedule s
+                on s.deleted is false
+                and s.group_id is null
+                and s.day <= timezone(t.tz, now())::date
+            inner join pin.questions q
+                on q.id = s.question_id and q.deleted is false
+            order by t.tz, s.day desc
+        "##;
+        let tzs = keys.iter().map(|k| k.0.clone()).collect::<Vec<_>>();
+        let rows = sqlx::query(query)
+            .bind(&tzs)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("error loading question of the day for timezones {:?}", e);
+                AppError::from(e)
+            })?;
+        let mut res = HashMap::new();
+        for row in rows {
+            let tz: String = row.try_get("tz").map_err(AppError::from)?;
+            let question = Question::from_row(&row).map_err(AppError::from)?;
+            res.insert(QuestionOfDayForTimezone(tz), question);
+        }
+        tracing::info!("loaded question of the day for {} timezones", res.len());
+        Ok(res)
+    }
+}
+
+/// A group's question of the day, from the group's own calendar. Rolls over
+/// with the same day boundary as `QOD_QUERY`
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct GroupQuestionOfDay(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<GroupQuestionOfDay> for PgLoader {
+    type Value = Question;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[GroupQuestionOfDay],
+    ) -> std::result::Result<HashMap<GroupQuestionOfDay, Self::Value>, Self::Error> {
+        tracing::info!("loading question of the day for {} groups", keys.len());
+        let query = r##"
+        select distinct on (s.group_id) q.*
+            from pin.question_schedule s
+            inner join pin.questions q
+                on q.id = s.question_id and q.deleted is false
+            where s.group_id in (select * from unnest($1))
+                and s.deleted is false
+                and s.day <= timezone('America/New_York', now())::date
+            order by s.group_id, s.day desc
+        "##;
+        let keys = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let res: Vec<Question> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("error loading question of the day for groups {:?}", e);
+                AppError::from(e)
+            })?;
+        tracing::info!("loaded question of the day for {} groups", res.len());
+        let res = res
+            .into_iter()
+            .filter_map(|q| Some((GroupQuestionOfDay(q.group_id?), q)))
+            .collect::<HashMap<_, _>>();
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct QuestionId(pub i64);
+
+#[async_trait::async_trait]
+impl async_graphql::dataloader::Loader<QuestionId> for PgLoader {
+    type Value = Question;
+    type Error = std::sync::Arc<AppError>;
+
+    async fn load(
+        &self,
+        keys: &[QuestionId],
+    ) -> std::result::Result<HashMap<QuestionId, Self::Value>, Self::Error> {
+        tracing::info!("loading {} questions", keys.len());
+        let query = r##"
+        select * from pin.questions
+            where id in (select * from unnest($1))
+        "##;
+        let keys = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let res: Vec<Question> = sqlx::query_as(query)
+            .bind(&keys)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("error loading questions {:?}", e);
+                AppError::from(e)
+            })?;
+        tracing::info!("loaded {} questions", res.len());
+        let res = res
+            .into_iter()
+            .map(|q| (QuestionId(q.id), q))
+            .collect::<HashMap<_, _>>();
+        Ok(res)
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct MultiOptionsForQuestion(pub i64);
 
@@ -399,8 +811,10 @@ impl async_graphql::dataloader::Loader<PinionForQuestion> for PgLoader {
     }
 }
 
+/// Comments on a pinion as seen by a user, comments by users blocked by or
+/// blocking the user are left out
 #[derive(Clone, Hash, PartialEq, Eq)]
-pub struct CommentsForPinion(pub i64);
+pub struct CommentsForPinion(pub i64, pub i64);
 
 #[async_trait::async_trait]
 impl async_graphql::dataloader::Loader<CommentsForPinion> for PgLoader {
@@ -413,31 +827,38 @@ impl async_graphql::dataloader::Loader<CommentsForPinion> for PgLoader {
     ) -> std::result::Result<HashMap<CommentsForPinion, Self::Value>, Self::Error> {
         tracing::info!("loading comments for {} pinions", keys.len());
         let query = r##"
-        select * from pin.comments
+        select k.user_id as viewer_id, c.*
+            from unnest($1::bigint[], $2::bigint[]) as k(pinion_id, user_id)
+            inner join pin.comments c on c.pinion_id = k.pinion_id
             where
-                deleted is false and
-                pinion_id in (select * from unnest($1))
-            order by created asc
+                c.deleted is false and
+                not pin.is_blocked(k.user_id, c.user_id)
+            order by c.created asc
         "##;
-        let p_ids = keys.iter().map(|ga| ga.0).collect::<Vec<_>>();
-        let res: Vec<Comment> = sqlx::query_as(query)
+        let p_ids = keys.iter().map(|k| k.0).collect::<Vec<_>>();
+        let u_ids = keys.iter().map(|k| k.1).collect::<Vec<_>>();
+        let rows = sqlx::query(query)
             .bind(&p_ids)
+            .bind(&u_ids)
             .fetch_all(&self.pool)
             .await
             .map_err(|e| {
-                tracing::error!("error loading pinions {:?}", e);
+                tracing::error!("error loading comments {:?}", e);
                 AppError::from(e)
             })?;
-        tracing::info!("loaded {} comments", res.len());
-        let res = res.into_iter().fold(HashMap::new(), |mut acc, comment| {
-            {
-                let e = acc
-                    .entry(CommentsForPinion(comment.pinion_id))
-                    .or_insert_with(Vec::new);
-                e.push(comment);
-            }
-            acc
-        });
+        tracing::info!("loaded {} comments", rows.len());
+        let mut res = HashMap::new();
+        for row in rows {
+            let viewer_id: i64 = row.try_get("viewer_id").map_err(AppError::from)?;
+            let comment = Comment::from_row(&row).map_err(AppError::from)?;
+            res.entry(CommentsForPinion(comment.pinion_id, viewer_id))
+                .or_insert_with(Vec::new)
+                .push(comment);
+        }
+        // pinions without comments get an empty list
+        for k in keys {
+            res.entry(k.clone()).or_insert_with(Vec::new);
+        }
         Ok(res)
     }
 }
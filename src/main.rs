@@ -1,37 +1,106 @@
-use async_graphql::{dataloader::HashMapCache, EmptySubscription};
-use async_graphql_warp::GraphQLResponse;
+use async_graphql::dataloader::HashMapCache;
+use async_graphql_warp::{graphql_protocol, GraphQLResponse, GraphQLWebSocket};
 use sqlx::postgres::PgConnectOptions;
-use sqlx::{ConnectOptions, PgPool};
-use std::collections::HashMap;
+use sqlx::{ConnectOptions, FromRow, PgPool, Row};
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::time::Duration;
 use warp::{hyper::Method, Filter};
 
+mod cli;
 mod config;
 mod crypto;
 mod error;
+mod events;
 mod loaders;
 mod models;
+mod phone;
+mod schedule;
 mod schema;
+mod sms;
+mod tally;
 
 use crate::crypto::b64_decode;
 use crate::error::LogError;
-use crate::loaders::QOD_QUERY;
-use crate::models::{ChallengePhone, Question, QuestionOptionCount};
+use crate::models::{ChallengePhone, CurrentSession, UserAgent};
 use error::{AppError, Result};
 use loaders::PgLoader;
 use models::User;
-use schema::{MutationRoot, QueryRoot, Schema};
+use schema::{MutationRoot, QueryRoot, Schema, SubscriptionRoot};
 
 lazy_static::lazy_static! {
     pub static ref CONFIG: config::Config = config::Config::load();
 }
 
+/// Look up the user and session associated with a clear auth token.
+/// The session's `last_seen` is bumped at most once a minute.
+async fn load_session_user(pool: &PgPool, auth: &str) -> Option<(User, CurrentSession)> {
+    let hash = crypto::hmac_sign(auth);
+    let row = sqlx::query(
+        r##"
+        select
+            u.*, p.number as phone_number, p.verified as phone_verified,
+            p.verification_sent as phone_verification_sent,
+            p.verification_attempts as phone_verification_attempts,
+            pr.name,
+            at.id as auth_token_id
+        from pin.users u
+            inner join pin.auth_tokens at on u.id = at.user_id
+            inner join pin.phones p on u.id = p.user_id
+                and p.deleted is false
+                and p.pending is false
+            left outer join pin.profiles pr on u.id = pr.user_id
+        where at.hash = $1
+            and at.deleted is false
+            and at.expires > now()
+            and u.deleted is false
+            and (pr.deleted is false or pr.deleted is null)
+            "##,
+    )
+    .bind(hash)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        if matches!(e, sqlx::Error::RowNotFound) {
+            tracing::info!("no user logged in");
+        } else {
+            tracing::error!("error {:?}", e);
+        }
+        AppError::from(e)
+    })
+    .ok()?;
+    let user = User::from_row(&row)
+        .map_err(AppError::from)
+        .log_error_msg(|| "error decoding session user")
+        .ok()?;
+    let session = CurrentSession {
+        id: row
+            .try_get("auth_token_id")
+            .map_err(AppError::from)
+            .log_error_msg(|| "error decoding session id")
+            .ok()?,
+    };
+    sqlx::query(
+        r##"
+        update pin.auth_tokens
+            set last_seen = now()
+            where id = $1
+                and (last_seen is null or last_seen < now() - interval '1 minute')
+        "##,
+    )
+    .bind(session.id)
+    .execute(pool)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error updating session last_seen")
+    .ok();
+    Some((user, session))
+}
+
 #[tokio::main]
 async fn main() {
     if let Err(e) = run().await {
-        eprintln!("Error running server: {e}");
+        eprintln!("Error: {e:?}");
         std::process::exit(1);
     }
 }
@@ -57,6 +126,11 @@ async fn run() -> Result<()> {
         .log_slow_statements(log::LevelFilter::Warn, std::time::Duration::new(5, 0));
     let pool = sqlx::PgPool::connect_with(pg_opt).await?;
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        return cli::run(&pool, &args).await;
+    }
+
     let status = warp::path("status").and(warp::get()).map(move || {
         #[derive(serde::Serialize)]
         struct Status<'a> {
@@ -76,8 +150,16 @@ async fn run() -> Result<()> {
 
     let index = warp::any().and(warp::path::end()).map(|| "hello");
 
-    let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+    let sms = sms::from_config()?;
+    tracing::info!(backend = %CONFIG.sms_backend, "configured sms backend");
+
+    let events = events::Events::default();
+
+    let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
         .data(pool.clone())
+        .data(sms)
+        .data(events.clone())
+        .extension(loaders::OperationLoader::new(pool.clone()))
         .finish();
 
     let move_pool = pool.clone();
@@ -90,49 +172,25 @@ async fn run() -> Result<()> {
         .and(warp::filters::cookie::optional(
             &CONFIG.cookie_challenge_phone_name,
         ))
+        .and(warp::filters::header::optional("user-agent"))
         .and(async_graphql_warp::graphql(schema.clone()))
         .and_then(
             |pool: PgPool,
              auth_cookie: Option<String>,
              auth_header: Option<String>,
              challenge_phone_cookie: Option<String>,
+             user_agent: Option<String>,
              (schema, mut request): (Schema, async_graphql::Request)| async move {
                 if let Some(auth) = auth_cookie.or(auth_header) {
-                    let hash = crypto::hmac_sign(&auth);
-                    let u: Result<User> = sqlx::query_as(
-                        r##"
-                        select
-                            u.*, p.number as phone_number, p.verified as phone_verified,
-                            p.verification_sent as phone_verification_sent,
-                            p.verification_attempts as phone_verification_attempts,
-                            pr.name
-                        from pin.users u
-                            inner join pin.auth_tokens at on u.id = at.user_id
-                            inner join pin.phones p on u.id = p.user_id
-                            left outer join pin.profiles pr on u.id = pr.user_id
-                        where at.hash = $1
-                            and at.deleted is false
-                            and at.expires > now()
-                            and u.deleted is false
-                            and (pr.deleted is false or pr.deleted is null)
-                            "##,
-                    )
-                    .bind(hash)
-                    .fetch_one(&pool)
-                    .await
-                    .map_err(|e| {
-                        if matches!(e, sqlx::Error::RowNotFound) {
-                            tracing::info!("no user logged in");
-                        } else {
-                            tracing::error!("error {:?}", e);
-                        }
-                        AppError::from(e)
-                    });
-                    if let Ok(u) = u {
+                    if let Some((u, session)) = load_session_user(&pool, &auth).await {
                         tracing::info!(user = %u.handle, user_id = %u.id, "found user for request");
                         request.data.insert(u);
+                        request.data.insert(session);
                     }
                 }
+                if let Some(user_agent) = user_agent {
+                    request.data.insert(UserAgent(user_agent));
+                }
                 let loader = async_graphql::dataloader::DataLoader::with_cache(
                     PgLoader::new(pool),
                     tokio::spawn,
@@ -175,6 +233,61 @@ async fn run() -> Result<()> {
             },
         );
 
+    // subscriptions authenticate with the same cookie/header as posts. Clients that
+    // can't set either can send the auth token in the `connection_init` payload
+    let move_pool = pool.clone();
+    let move_schema = schema.clone();
+    let graphql_ws = warp::path!("api" / "graphql" / "ws")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(warp::filters::cookie::optional(&CONFIG.auth_cookie_name))
+        .and(warp::filters::header::optional(&CONFIG.auth_header_name))
+        .and(graphql_protocol())
+        .map(
+            move |ws: warp::ws::Ws,
+                  auth_cookie: Option<String>,
+                  auth_header: Option<String>,
+                  protocol| {
+                let pool = move_pool.clone();
+                let schema = move_schema.clone();
+                let reply = ws.on_upgrade(move |socket| async move {
+                    let mut data = async_graphql::Data::default();
+                    let mut logged_in = false;
+                    if let Some(auth) = auth_cookie.or(auth_header) {
+                        if let Some((u, session)) = load_session_user(&pool, &auth).await {
+                            tracing::info!(user = %u.handle, user_id = %u.id, "found user for subscription");
+                            data.insert(u);
+                            data.insert(session);
+                            logged_in = true;
+                        }
+                    }
+                    GraphQLWebSocket::new(socket, schema, protocol)
+                        .with_data(data)
+                        .on_connection_init(move |payload| async move {
+                            let mut data = async_graphql::Data::default();
+                            let auth = payload
+                                .get(&CONFIG.auth_header_name)
+                                .and_then(|v| v.as_str());
+                            if let (false, Some(auth)) = (logged_in, auth) {
+                                if let Some((u, session)) = load_session_user(&pool, auth).await {
+                                    tracing::info!(user = %u.handle, user_id = %u.id, "found user for subscription");
+                                    data.insert(u);
+                                    data.insert(session);
+                                }
+                            }
+                            Ok(data)
+                        })
+                        .serve()
+                        .await
+                });
+                warp::reply::with_header(
+                    reply,
+                    "Sec-WebSocket-Protocol",
+                    protocol.sec_websocket_protocol(),
+                )
+            },
+        );
+
     let graphiql = warp::path!("_" / "graphiql")
         .and(warp::path::end())
         .and(warp::get())
@@ -184,6 +297,7 @@ async fn run() -> Result<()> {
                 .body(
                     async_graphql::http::GraphiQLSource::build()
                         .endpoint("/api/graphql")
+                        .subscription_endpoint("/api/graphql/ws")
                         .finish(),
                 )
         });
@@ -208,6 +322,7 @@ async fn run() -> Result<()> {
     let routes = index
         .or(index_options)
         .or(graphql_post)
+        .or(graphql_ws)
         .or(graphiql)
         .or(graphql_options)
         .or(favicon)
@@ -215,131 +330,38 @@ async fn run() -> Result<()> {
         .with(cors)
         .with(warp::trace::request());
 
-    async fn tally_question_of_the_day(pool: PgPool) {
-        // todo: instead of just sleep, select from either a timer
-        //       or a channel so that other code can trigger a refresh
+    async fn fill_question_schedule(pool: PgPool) {
         loop {
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            let question: Result<Question> = sqlx::query_as(QOD_QUERY)
-                .fetch_one(&pool)
-                .await
-                .map_err(AppError::from)
-                .log_error_msg(|| "background: error loading question of the day");
-            if question.is_err() {
-                continue;
-            }
-            let question = question.unwrap();
-
-            let tr = pool
-                .begin()
-                .await
-                .map_err(AppError::from)
-                .log_error_msg(|| "background: error starting transaction {:?}");
-            if tr.is_err() {
-                continue;
-            }
-            let mut tr = tr.unwrap();
-            let options = Question::get_options(question.id, &mut tr)
-                .await
-                .log_error_msg(|| "background: error getting question options");
-            if options.is_err() {
-                continue;
-            }
-            let options = options.unwrap();
-            let option_counts = QuestionOptionCount::get_option_counts(question.id, &mut tr)
-                .await
-                .log_error_msg(|| "background: error getting option counts");
-            if option_counts.is_err() {
-                continue;
-            }
-            let option_counts = option_counts.unwrap();
-            let option_counts = option_counts
-                .into_iter()
-                .map(|count| (count.multi_selection, count.count))
-                .collect::<HashMap<i64, i64>>();
-            let option_counts = options
-                .into_iter()
-                .map(|opt| (opt.id, option_counts.get(&opt.id).unwrap_or(&0)))
-                .collect::<Vec<_>>();
-            for (multi_selection, count) in option_counts {
-                sqlx::query(
-                    r##"
-                    insert into pin.question_multi_option_tallies
-                    (question_id, multi_selection, count)
-                    values
-                    ($1, $2, $3)
-                    on conflict (question_id, multi_selection) where deleted is false
-                    do update set
-                        count = $3
-                    "##,
-                )
-                .bind(question.id)
-                .bind(multi_selection)
-                .bind(count)
-                .execute(&mut *tr)
-                .await
-                .map_err(AppError::from)
-                .log_error_msg(|| "background: error upserting multi selection count")
-                .ok();
-            }
-            tr.commit()
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            schedule::fill(&pool)
                 .await
-                .map_err(AppError::from)
-                .log_error_msg(|| "background: error commiting changes")
+                .log_error_msg(|| "background: error filling question schedule")
                 .ok();
         }
     }
 
-    tokio::spawn(tally_question_of_the_day(pool.clone()));
-
-    async fn set_question_of_the_day(pool: PgPool) {
-        loop {
-            tokio::time::sleep(Duration::from_secs(30)).await;
-            let question: Result<Question> = sqlx::query_as(QOD_QUERY)
-                .fetch_one(&pool)
-                .await
-                .map_err(|e| {
-                    tracing::error!("background: error loading question of the day {:?}", e);
-                    AppError::from(e)
-                });
-            if question.is_err() {
-                continue;
-            }
-            let question = question.unwrap();
-            if matches!(question.used, None) {
-                let tr = pool.begin().await.map_err(|e| {
-                    tracing::error!("background: error starting transaction {:?}", e);
-                    AppError::from(e)
-                });
-                if tr.is_err() {
-                    continue;
-                }
-                let mut tr = tr.unwrap();
-                let question = Question::mark_used(question.id, &mut tr)
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("background: error marking question used {:?}", e);
-                        e
-                    });
-                if question.is_err() {
-                    continue;
-                }
-                tr.commit()
-                    .await
-                    .map_err(|e| {
-                        tracing::error!(
-                            "background: error marking question used transaction {:?}",
-                            e
-                        );
-                        AppError::from(e)
-                    })
-                    .ok();
-            }
-        }
+    // numbers added before normalization was enforced, must run before hashing
+    phone::normalize_existing(&pool)
+        .await
+        .map(|r| r.log())
+        .log_error_msg(|| "error normalizing phone numbers")
+        .ok();
+    // numbers added before hashed contact discovery need their hmac
+    phone::backfill_hashes(&pool, false)
+        .await
+        .log_error_msg(|| "error backfilling phone number hashes")
+        .ok();
+    // make sure there's a question of the day before taking requests
+    schedule::fill(&pool)
+        .await
+        .log_error_msg(|| "error filling question schedule")
+        .ok();
+    tokio::spawn(fill_question_schedule(pool.clone()));
+    tokio::spawn(events::listen(pool.clone(), events));
+    if let Some(interval) = CONFIG.tally_reconcile_seconds {
+        tokio::spawn(tally::maintain(pool.clone(), interval));
     }
 
-    tokio::spawn(set_question_of_the_day(pool.clone()));
-
     if !CONFIG.secure_cookie {
         tracing::warn!("*** SECURE COOKIE IS DISABLED ***");
     }
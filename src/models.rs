@@ -1,12 +1,14 @@
 use crate::error::LogError;
 use crate::loaders::{
-    AppLoader, CommentsForPinion, FriendsForUserId, GroupAssociationsForUserId,
+    AppLoader, CommentsForPinion, FriendsForUserId, GroupAssociationsForUserId, GroupId,
+    GroupInvitesForUserId, GroupQuestionOfDay, GroupSummaryForQuestionId, MembersForGroupId,
     MultiOptionsForQuestion, PinionForQuestion, PinionsOfFriendsForUserQuestionId,
-    ProfileForUserId, QuestionOfDay, UserForPhone, UserId,
+    PinionsOfGroupForQuestionId, ProfileForUserId, QuestionId, QuestionOfDay,
+    QuestionOfDayForTimezone, UserForPhone, UserId,
 };
 use crate::{AppError, Result};
 use async_graphql::{Context, ErrorExtensions, FieldResult, Object, ResultExt};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use sqlx::PgPool;
 use std::collections::HashMap;
 
@@ -15,7 +17,41 @@ pub struct ChallengePhone {
     pub number: String,
 }
 
+/// The auth token (session) that authenticated the current request
+#[derive(Clone)]
+pub struct CurrentSession {
+    pub id: i64,
+}
+
+impl CurrentSession {
+    /// False once the session is logged out, revoked or expired
+    pub async fn is_active(&self, pool: &PgPool) -> Result<bool> {
+        let (active,): (bool,) = sqlx::query_as(
+            r##"
+            select exists(
+                select 1 from pin.auth_tokens at
+                    inner join pin.users u on u.id = at.user_id
+                where at.id = $1
+                    and at.deleted is false
+                    and at.expires > now()
+                    and u.deleted is false
+            )
+            "##,
+        )
+        .bind(self.id)
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)?;
+        Ok(active)
+    }
+}
+
+/// The user-agent header of the current request
+#[derive(Clone)]
+pub struct UserAgent(pub String);
+
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct BaseUser {
     pub id: i64,
     pub deleted: bool,
@@ -24,6 +60,7 @@ pub struct BaseUser {
 }
 
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct User {
     pub id: i64,
     pub handle: String,
@@ -31,6 +68,7 @@ pub struct User {
     pub phone_verified: Option<DateTime<Utc>>,
     pub phone_verification_sent: Option<DateTime<Utc>>,
     pub phone_verification_attempts: i32,
+    pub admin: bool,
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
@@ -56,6 +94,7 @@ impl User {
            where u.id = $1
                and u.deleted is false
                and p.deleted is false
+               and p.pending is false
                and (pr.deleted is false or pr.deleted is null)
            "##,
         )
@@ -65,10 +104,45 @@ impl User {
         .map_err(AppError::from)?;
         Ok(user)
     }
+    pub async fn fetch_user_by_handle(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        handle: &str,
+    ) -> Result<Option<User>> {
+        let user = sqlx::query_as(
+            r##"
+           select
+               u.*,
+               p.number as phone_number,
+               p.verified as phone_verified,
+               p.verification_sent as phone_verification_sent,
+               p.verification_attempts as phone_verification_attempts,
+               pr.name as name
+           from pin.users u
+               inner join pin.phones p on p.user_id = u.id
+               left outer join pin.profiles pr on pr.user_id = u.id
+           where u.handle = $1
+               and u.deleted is false
+               and p.deleted is false
+               and p.pending is false
+               and (pr.deleted is false or pr.deleted is null)
+           "##,
+        )
+        .bind(handle)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(user)
+    }
+    /// Look up a user by any formatting of their phone number,
+    /// numbers that can't be parsed match nobody
     pub async fn fetch_user_by_number(
         tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
         phone_number: &str,
     ) -> Result<Option<User>> {
+        let phone_number = match crate::phone::normalize(phone_number) {
+            Ok(n) => n,
+            Err(_) => return Ok(None),
+        };
         let user = sqlx::query_as(
             r##"
            select
@@ -84,6 +158,7 @@ impl User {
            where p.number = $1
                and u.deleted is false
                and p.deleted is false
+               and p.pending is false
                and (pr.deleted is false or pr.deleted is null)
            "##,
         )
@@ -93,6 +168,27 @@ impl User {
         .map_err(AppError::from)?;
         Ok(user)
     }
+
+    /// The question of the day for this user's local day, users that
+    /// haven't set a timezone follow the server's day
+    pub async fn local_question_of_day(&self, ctx: &Context<'_>) -> FieldResult<Question> {
+        let loader = ctx.data_unchecked::<AppLoader>();
+        let timezone = loader
+            .load_one(ProfileForUserId(self.id))
+            .await?
+            .and_then(|p| p.timezone);
+        let r = match timezone {
+            Some(tz) => loader.load_one(QuestionOfDayForTimezone(tz)).await?,
+            None => loader.load_one(QuestionOfDay {}).await?,
+        };
+        // nothing is scheduled yet on the user's day, e.g. behind the server's
+        // zone right after the first scheduled day
+        r.ok_or_else(|| {
+            AppError::DBNotFound(sqlx::Error::RowNotFound)
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "NO_QUESTION_OF_DAY"))
+        })
+    }
 }
 
 #[Object]
@@ -140,6 +236,12 @@ impl User {
         self.phone_verified
     }
 
+    /// Whether this user can author and schedule questions
+    async fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// All friendships and pending requests, sent and received
     async fn friends(&self, ctx: &Context<'_>) -> FieldResult<Vec<Friend>> {
         let r = ctx
             .data_unchecked::<AppLoader>()
@@ -149,6 +251,96 @@ impl User {
         Ok(r)
     }
 
+    /// Friend requests waiting for this user to accept or decline, newest first
+    async fn incoming_friend_requests(&self, ctx: &Context<'_>) -> FieldResult<Vec<Friend>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(FriendsForUserId(self.id))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|f| f.accepted.is_none() && f.acceptor_id == self.id)
+            .collect();
+        Ok(r)
+    }
+
+    /// Friend requests this user sent that haven't been accepted yet, newest first
+    async fn outgoing_friend_requests(&self, ctx: &Context<'_>) -> FieldResult<Vec<Friend>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(FriendsForUserId(self.id))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|f| f.accepted.is_none() && f.requestor_id == self.id)
+            .collect();
+        Ok(r)
+    }
+
+    /// Accepted friendships
+    async fn accepted_friends(&self, ctx: &Context<'_>) -> FieldResult<Vec<Friend>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(FriendsForUserId(self.id))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|f| f.accepted.is_some())
+            .collect();
+        Ok(r)
+    }
+
+    /// Streaks and answering stats, with days in the user's timezone
+    async fn stats(&self, ctx: &Context<'_>) -> FieldResult<UserStats> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let timezone = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(ProfileForUserId(self.id))
+            .await?
+            .and_then(|p| p.timezone);
+        let stats = UserStats::fetch(pool, self.id, timezone.as_deref())
+            .await
+            .log_error_msg(|| "error loading user stats")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(stats)
+    }
+
+    /// Friends who most often answer the same way as this user, over the last
+    /// 30 days or all time. Friends with fewer than `min_shared` questions in
+    /// common are left out
+    async fn most_similar_friends(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] last_30_days: bool,
+        #[graphql(default = 3)] min_shared: i64,
+        #[graphql(default = 10)] limit: usize,
+    ) -> FieldResult<Vec<FriendUser>> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let agreements = friend_agreements(self.id, pool).await.extend()?;
+        let mut ranked = agreements
+            .values()
+            .map(|a| (a.friend_id, a.window(last_30_days)))
+            .filter(|(_, w)| w.shared >= min_shared.max(1))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a_id, a), (b_id, b)| {
+            b.agreed_fraction()
+                .partial_cmp(&a.agreed_fraction())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.shared.cmp(&a.shared))
+                .then(a_id.cmp(b_id))
+        });
+        ranked.truncate(limit);
+        let users = ctx
+            .data_unchecked::<AppLoader>()
+            .load_many(ranked.iter().map(|(id, _)| UserId(*id)))
+            .await?;
+        let r = ranked
+            .into_iter()
+            .filter_map(|(id, _)| users.get(&UserId(id)).cloned().map(FriendUser::from))
+            .collect();
+        Ok(r)
+    }
+
     async fn group_associations(&self, ctx: &Context<'_>) -> FieldResult<Vec<GroupAssociation>> {
         let r = ctx
             .data_unchecked::<AppLoader>()
@@ -158,14 +350,20 @@ impl User {
         Ok(r)
     }
 
-    async fn question_of_day(&self, ctx: &Context<'_>) -> FieldResult<Question> {
+    /// Pending invites to join groups
+    async fn group_invites(&self, ctx: &Context<'_>) -> FieldResult<Vec<GroupInvite>> {
         let r = ctx
             .data_unchecked::<AppLoader>()
-            .load_one(QuestionOfDay {})
+            .load_one(GroupInvitesForUserId(self.id))
             .await?
-            .unwrap();
+            .unwrap_or_default();
         Ok(r)
     }
+
+    /// The question of the day, relative to the user's local day
+    async fn question_of_day(&self, ctx: &Context<'_>) -> FieldResult<Question> {
+        self.local_question_of_day(ctx).await
+    }
     async fn created(&self) -> DateTime<Utc> {
         self.created
     }
@@ -174,6 +372,176 @@ impl User {
     }
 }
 
+/// Answering history of a user
+#[derive(Clone, Debug, Default)]
+pub struct UserStats {
+    pub current_streak: i64,
+    pub longest_streak: i64,
+    pub total_pinions: i64,
+    pub comments_made: i64,
+    // answered questions with at least two responses, and how many of those the
+    // user picked the most popular answer for
+    pub contested: i64,
+    pub in_majority: i64,
+}
+
+impl UserStats {
+    /// Streaks count global question days, a day is answered when the user answered
+    /// that day's question on that day in their timezone (`America/New_York` when
+    /// they haven't set one). Days that weren't question days don't break a streak
+    pub async fn fetch(pool: &PgPool, user_id: i64, timezone: Option<&str>) -> Result<UserStats> {
+        let days: Vec<(NaiveDate, bool, NaiveDate)> = sqlx::query_as(
+            r##"
+            select s.day,
+                exists(
+                    select 1 from pin.pinions p
+                    where p.user_id = $1
+                        and p.question_id = s.question_id
+                        and timezone(coalesce($2, 'America/New_York'), p.created)::date = s.day
+                ) as answered,
+                timezone(coalesce($2, 'America/New_York'), now())::date as today
+            from pin.question_schedule s
+            where s.deleted is false
+                and s.group_id is null
+                and s.day <= timezone(coalesce($2, 'America/New_York'), now())::date
+                and s.day >= (
+                    select timezone(coalesce($2, 'America/New_York'), created)::date
+                    from pin.users where id = $1
+                )
+            order by s.day asc
+            "##,
+        )
+        .bind(user_id)
+        .bind(timezone)
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)?;
+        let (current_streak, longest_streak) = match days.last() {
+            Some((_, _, today)) => {
+                let answered = days
+                    .iter()
+                    .map(|(day, answered, _)| (*day, *answered))
+                    .collect::<Vec<_>>();
+                streaks(&answered, *today)
+            }
+            None => (0, 0),
+        };
+
+        let (total_pinions, comments_made, contested, in_majority): (i64, i64, i64, i64) =
+            sqlx::query_as(
+                r##"
+                with mine as (
+                    select question_id, multi_selection from pin.pinions
+                    where user_id = $1 and deleted is false
+                ), tallies as (
+                    select t.question_id, t.multi_selection, t.count,
+                        max(t.count) over (partition by t.question_id) as top_count,
+                        sum(t.count) over (partition by t.question_id) as total_count
+                    from pin.question_multi_option_tallies t
+                    where t.deleted is false
+                        and t.question_id in (select question_id from mine)
+                )
+                select
+                    (select count(*) from mine) as total_pinions,
+                    (
+                        select count(*) from pin.comments
+                        where user_id = $1 and deleted is false
+                    ) as comments_made,
+                    count(t.question_id) filter (where t.total_count >= 2) as contested,
+                    count(t.question_id)
+                        filter (where t.total_count >= 2 and t.count = t.top_count) as in_majority
+                from mine m
+                    inner join tallies t
+                        on t.question_id = m.question_id and t.multi_selection = m.multi_selection
+                "##,
+            )
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .map_err(AppError::from)?;
+        Ok(UserStats {
+            current_streak,
+            longest_streak,
+            total_pinions,
+            comments_made,
+            contested,
+            in_majority,
+        })
+    }
+}
+
+/// Current and longest runs of answered days in `days` (ascending question days).
+/// Today's question not being answered yet doesn't end the current streak
+fn streaks(days: &[(NaiveDate, bool)], today: NaiveDate) -> (i64, i64) {
+    let longest = days
+        .iter()
+        .fold((0, 0), |(run, longest), (_, answered)| {
+            let run = if *answered { run + 1 } else { 0 };
+            (run, longest.max(run))
+        })
+        .1;
+    let current = days
+        .iter()
+        .rev()
+        .skip_while(|(day, answered)| *day == today && !answered)
+        .take_while(|(_, answered)| *answered)
+        .count() as i64;
+    (current, longest)
+}
+
+#[Object]
+impl UserStats {
+    /// Consecutive question days answered, up to today
+    async fn current_streak(&self) -> i64 {
+        self.current_streak
+    }
+    async fn longest_streak(&self) -> i64 {
+        self.longest_streak
+    }
+    async fn total_pinions(&self) -> i64 {
+        self.total_pinions
+    }
+    async fn comments_made(&self) -> i64 {
+        self.comments_made
+    }
+    /// Answered questions where the user picked the most popular answer (ties
+    /// included), out of questions with at least two responses
+    async fn majority_count(&self) -> i64 {
+        self.in_majority
+    }
+    /// `majorityCount` as a percentage, null before any answered question has
+    /// other responses
+    async fn majority_rate(&self) -> Option<i64> {
+        (self.contested > 0)
+            .then(|| (self.in_majority as f64 / self.contested as f64 * 100.).round() as i64)
+    }
+}
+
+#[test]
+fn test_streaks() {
+    let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+    let days = |answered: &[bool]| {
+        answered
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (day(i as u32 + 1), *a))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(streaks(&[], day(1)), (0, 0));
+    assert_eq!(
+        streaks(&days(&[true, true, true, false, true, true]), day(6)),
+        (2, 3)
+    );
+    // today isn't over yet
+    assert_eq!(
+        streaks(&days(&[true, false, true, true, false]), day(5)),
+        (2, 2)
+    );
+    // yesterday was missed
+    assert_eq!(streaks(&days(&[true, true, false, false]), day(4)), (0, 2));
+    assert_eq!(streaks(&days(&[true, true, false]), day(4)), (0, 2));
+}
+
 #[derive(Clone, sqlx::FromRow)]
 pub struct SimpleUser {
     pub id: i64,
@@ -224,19 +592,17 @@ impl FriendUser {
 
     /// The user's human name, prefer loading user.profile.name
     async fn name(&self, ctx: &Context<'_>) -> FieldResult<Option<String>> {
-        let u = ctx.data_opt::<User>().expect("no current user");
         let p = ctx
             .data_unchecked::<AppLoader>()
-            .load_one(ProfileForUserId(u.id))
+            .load_one(ProfileForUserId(self.id))
             .await?;
         Ok(p.and_then(|p| p.name))
     }
 
     async fn profile(&self, ctx: &Context<'_>) -> FieldResult<Option<Profile>> {
-        let u = ctx.data_opt::<User>().expect("no current user");
         let p = ctx
             .data_unchecked::<AppLoader>()
-            .load_one(ProfileForUserId(u.id))
+            .load_one(ProfileForUserId(self.id))
             .await?;
         Ok(p)
     }
@@ -246,6 +612,15 @@ impl FriendUser {
     async fn phone_number(&self) -> &str {
         &self.phone_number
     }
+
+    /// How often the current user and this friend answer questions the same way,
+    /// null unless they're friends. Refreshed every few minutes
+    async fn agreement(&self, ctx: &Context<'_>) -> FieldResult<Option<Agreement>> {
+        let u = ctx.data_opt::<User>().expect("no current user");
+        let pool = ctx.data_unchecked::<PgPool>();
+        let agreements = friend_agreements(u.id, pool).await.extend()?;
+        Ok(agreements.get(&self.id).cloned())
+    }
 }
 
 #[derive(Clone, sqlx::FromRow)]
@@ -290,10 +665,12 @@ impl PotentialFriendUser {
 }
 
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct Profile {
     pub id: i64,
     pub user_id: i64,
     pub name: Option<String>,
+    pub timezone: Option<String>,
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
@@ -310,6 +687,10 @@ impl Profile {
     async fn name(&self) -> &Option<String> {
         &self.name
     }
+    /// The user's timezone, e.g. `America/Los_Angeles`
+    async fn timezone(&self) -> &Option<String> {
+        &self.timezone
+    }
 }
 
 #[derive(Clone)]
@@ -329,6 +710,69 @@ impl LoginSuccess {
 }
 
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct Session {
+    pub id: i64,
+    pub user_id: i64,
+    pub expires: DateTime<Utc>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+impl Session {
+    pub async fn fetch_active_for_user(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: i64,
+    ) -> Result<Vec<Session>> {
+        let sessions: Vec<Session> = sqlx::query_as(
+            r##"
+            select id, user_id, expires, last_seen, user_agent, created
+            from pin.auth_tokens
+            where user_id = $1
+                and deleted is false
+                and expires > now()
+            order by coalesce(last_seen, created) desc
+            "##,
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(sessions)
+    }
+}
+
+#[Object]
+impl Session {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    /// When this session was logged in
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    async fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+    /// The last time a request was made with this session, updated at most once a minute
+    async fn last_seen(&self) -> Option<DateTime<Utc>> {
+        self.last_seen
+    }
+    /// The user-agent of the device that logged in
+    async fn user_agent(&self) -> &Option<String> {
+        &self.user_agent
+    }
+    /// Whether this is the session making the current request
+    async fn current(&self, ctx: &Context<'_>) -> bool {
+        ctx.data_opt::<CurrentSession>()
+            .map(|s| s.id == self.id)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct VerificationCode {
     pub id: i64,
     pub user_id: i64,
@@ -340,6 +784,132 @@ pub struct VerificationCode {
 }
 
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct Password {
+    pub id: i64,
+    pub user_id: i64,
+    pub salt: String,
+    pub hash: String,
+    pub failed_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+impl Password {
+    /// Load the user's active password, locking the row so concurrent
+    /// attempts are counted one at a time
+    pub async fn fetch_for_user(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: i64,
+    ) -> Result<Option<Password>> {
+        let pw = sqlx::query_as(
+            r##"
+            select * from pin.passwords
+            where user_id = $1 and deleted is false
+            for update
+            "##,
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(pw)
+    }
+
+    /// Replace any existing password for the user with a new salt and hash
+    pub async fn set_for_user(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: i64,
+        salt: &str,
+        hash: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            r##"
+            update pin.passwords
+                set deleted = true, modified = now()
+                where user_id = $1 and deleted is false
+            "##,
+        )
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        sqlx::query(
+            r##"
+            insert into pin.passwords (user_id, salt, hash)
+                values ($1, $2, $3)
+            "##,
+        )
+        .bind(user_id)
+        .bind(salt)
+        .bind(hash)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(())
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked_until
+            .map(|until| until > Utc::now())
+            .unwrap_or(false)
+    }
+
+    /// Count a failed attempt, locking the password for `lockout_seconds`
+    /// once `max_attempts` failures have accumulated
+    pub async fn record_failure(
+        &self,
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        max_attempts: i32,
+        lockout_seconds: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r##"
+            update pin.passwords
+                set modified = now(),
+                failed_attempts = case
+                    when failed_attempts + 1 >= $2 then 0
+                    else failed_attempts + 1
+                end,
+                locked_until = case
+                    when failed_attempts + 1 >= $2 then now() + make_interval(secs => $3)
+                    else locked_until
+                end
+            where id = $1
+            "##,
+        )
+        .bind(self.id)
+        .bind(max_attempts)
+        .bind(lockout_seconds as f64)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(())
+    }
+
+    pub async fn clear_failures(
+        &self,
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<()> {
+        sqlx::query(
+            r##"
+            update pin.passwords
+                set modified = now(), failed_attempts = 0, locked_until = null
+            where id = $1
+            "##,
+        )
+        .bind(self.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct Phone {
     pub id: i64,
     pub number: String,
@@ -363,21 +933,31 @@ impl Phone {
 #[derive(Clone, sqlx::FromRow)]
 pub struct PhoneCheck {
     pub number: String,
+    pub normalized: Option<String>,
     pub signed_up: bool,
 }
 
 #[Object]
 impl PhoneCheck {
+    /// The number as it was submitted
     async fn number(&self) -> &str {
         &self.number
     }
+    /// The E.164 form of the number, null when it couldn't be parsed
+    async fn normalized_number(&self) -> &Option<String> {
+        &self.normalized
+    }
     async fn signed_up(&self) -> bool {
         self.signed_up
     }
     async fn user(&self, ctx: &Context<'_>) -> FieldResult<PotentialFriendUser> {
         let r = ctx
             .data_unchecked::<AppLoader>()
-            .load_one(UserForPhone(self.number.clone()))
+            .load_one(UserForPhone(
+                self.normalized
+                    .clone()
+                    .unwrap_or_else(|| self.number.clone()),
+            ))
             .await?
             .ok_or_else(|| AppError::E(format!("missing user for phone {}", self.number)).extend())?
             .into();
@@ -385,49 +965,458 @@ impl PhoneCheck {
     }
 }
 
+/// A hashed contact that belongs to a signed up user
+#[derive(Clone, sqlx::FromRow)]
+pub struct PhoneHashMatch {
+    pub hash: String,
+    pub id: i64,
+    pub handle: String,
+    pub is_friend: bool,
+}
+
+#[Object]
+impl PhoneHashMatch {
+    /// The hash as it was submitted
+    async fn hash(&self) -> &str {
+        &self.hash
+    }
+    async fn user(&self) -> PotentialFriendUser {
+        PotentialFriendUser {
+            id: self.id,
+            handle: self.handle.clone(),
+            is_friend: self.is_friend,
+        }
+    }
+}
+
 #[derive(Debug, Clone, sqlx::FromRow)]
-pub struct Friend {
+#[allow(dead_code)]
+pub struct Invite {
     pub id: i64,
-    pub requestor_id: i64,
-    pub acceptor_id: i64,
+    pub inviter_id: i64,
+    pub number: String,
+    pub invitee_id: Option<i64>,
+    pub sent: Option<DateTime<Utc>>,
     pub accepted: Option<DateTime<Utc>>,
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
 }
 
-#[Object]
-impl Friend {
-    async fn relationship_id(&self) -> String {
-        self.id.to_string()
+impl Invite {
+    /// The code included in the invite link, the invite's id and its signature
+    pub fn code(&self) -> String {
+        format!(
+            "{}.{}",
+            self.id,
+            crate::crypto::hmac_sign(&invite_signed_text(self.id))
+        )
     }
-    async fn accepted(&self) -> Option<DateTime<Utc>> {
-        self.accepted
+
+    /// The invite id of a code, if the code was issued by us
+    pub fn parse_code(code: &str) -> Option<i64> {
+        let (id, sig) = code.trim().split_once('.')?;
+        let id = id.parse::<i64>().ok()?;
+        crate::crypto::hmac_verify(&invite_signed_text(id), sig).then_some(id)
     }
-    async fn user(&self, ctx: &Context<'_>) -> FieldResult<FriendUser> {
-        let u = ctx.data_opt::<User>().expect("no current user");
-        let other_user_id = if self.acceptor_id != u.id {
-            self.acceptor_id
-        } else {
-            self.requestor_id
+
+    /// Attach a user who signed up from an invite link to the invite, so they're
+    /// friended with the inviter once verified even if it was sent to another number.
+    /// An invite can only be claimed once
+    pub async fn claim(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        code: &str,
+        user_id: i64,
+    ) -> Result<bool> {
+        let id = match Invite::parse_code(code) {
+            Some(id) => id,
+            None => return Ok(false),
         };
-        let r = ctx
-            .data_unchecked::<AppLoader>()
-            .load_one(UserId(other_user_id))
-            .await?
-            .ok_or_else(|| {
-                AppError::E(format!(
-                    "missing expected friend user {} of user {}",
-                    other_user_id, u.id
-                ))
-                .extend()
-            })?
+        let res = sqlx::query(
+            r##"
+            update pin.invites
+                set invitee_id = $2, modified = now()
+                where id = $1
+                    and inviter_id != $2
+                    and invitee_id is null
+                    and deleted is false
+                    and accepted is null
+            "##,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Accept the open invites for a newly verified number or claimed by the user,
+    /// befriending each inviter. Returns the number of new friendships
+    pub async fn accept_for_user(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: i64,
+        number: &str,
+    ) -> Result<u64> {
+        let res = sqlx::query(
+            r##"
+            with accepted as (
+                update pin.invites
+                    set accepted = now(), invitee_id = $1, modified = now()
+                    where deleted is false
+                        and accepted is null
+                        and inviter_id != $1
+                        and (number = $2 or invitee_id = $1)
+                    returning inviter_id
+            )
+            insert into pin.friends (requestor_id, acceptor_id, accepted)
+                select distinct a.inviter_id, $1::bigint, now()
+                from accepted a
+                    inner join pin.users u on u.id = a.inviter_id and u.deleted is false
+                where not pin.is_blocked($1, a.inviter_id)
+            on conflict do nothing
+            "##,
+        )
+        .bind(user_id)
+        .bind(number)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(res.rows_affected())
+    }
+}
+
+fn invite_signed_text(id: i64) -> String {
+    format!("invite:{id}")
+}
+
+#[Object]
+impl Invite {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    /// The invited number in E.164 form
+    async fn number(&self) -> &str {
+        &self.number
+    }
+    /// When the invite was texted. Null when the number already got an invite
+    /// text recently, the invite still counts when they sign up
+    async fn sent(&self) -> Option<DateTime<Utc>> {
+        self.sent
+    }
+    async fn accepted(&self) -> Option<DateTime<Utc>> {
+        self.accepted
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+#[test]
+fn test_invite_code_round_trip() {
+    let invite = Invite {
+        id: 42,
+        inviter_id: 1,
+        number: "+15551234567".into(),
+        invitee_id: None,
+        sent: None,
+        accepted: None,
+        deleted: false,
+        created: Utc::now(),
+        modified: Utc::now(),
+    };
+    let code = invite.code();
+    assert_eq!(Invite::parse_code(&code), Some(42));
+    assert_eq!(Invite::parse_code(&code.replacen("42.", "43.", 1)), None);
+    assert_eq!(Invite::parse_code("42"), None);
+    assert_eq!(Invite::parse_code("nope.abc"), None);
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct Friend {
+    pub id: i64,
+    pub requestor_id: i64,
+    pub acceptor_id: i64,
+    pub accepted: Option<DateTime<Utc>>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+impl Friend {
+    pub async fn fetch(pool: &PgPool, id: i64) -> Result<Option<Friend>> {
+        let f = sqlx::query_as(r##"select * from pin.friends where id = $1 and deleted is false"##)
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(AppError::from)?;
+        Ok(f)
+    }
+
+    pub async fn fetch_for_update(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+    ) -> Result<Option<Friend>> {
+        let f = sqlx::query_as(
+            r##"select * from pin.friends where id = $1 and deleted is false for update"##,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(f)
+    }
+
+    pub async fn delete(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>, id: i64) -> Result<Friend> {
+        let f = sqlx::query_as(
+            r##"
+            update pin.friends
+                set deleted = true, modified = now()
+                where id = $1
+                returning *
+            "##,
+        )
+        .bind(id)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(f)
+    }
+}
+
+#[Object]
+impl Friend {
+    async fn relationship_id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn accepted(&self) -> Option<DateTime<Utc>> {
+        self.accepted
+    }
+    /// When the request was sent
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    async fn user(&self, ctx: &Context<'_>) -> FieldResult<FriendUser> {
+        let u = ctx.data_opt::<User>().expect("no current user");
+        let other_user_id = if self.acceptor_id != u.id {
+            self.acceptor_id
+        } else {
+            self.requestor_id
+        };
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(UserId(other_user_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected friend user {} of user {}",
+                    other_user_id, u.id
+                ))
+                .extend()
+            })?
             .into();
         Ok(r)
     }
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct Block {
+    pub id: i64,
+    pub blocker_id: i64,
+    pub blocked_id: i64,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+impl Block {
+    /// Whether either user has blocked the other
+    pub async fn exists_between(pool: &PgPool, user_id: i64, other_id: i64) -> Result<bool> {
+        let (exists,): (bool,) = sqlx::query_as(
+            r##"
+            select pin.is_blocked($1, $2)
+            "##,
+        )
+        .bind(user_id)
+        .bind(other_id)
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)?;
+        Ok(exists)
+    }
+
+    pub async fn fetch_for_user(pool: &PgPool, blocker_id: i64) -> Result<Vec<Block>> {
+        let blocks = sqlx::query_as(
+            r##"
+            select b.* from pin.blocks b
+                inner join pin.users u on u.id = b.blocked_id
+            where b.blocker_id = $1
+                and b.deleted is false
+                and u.deleted is false
+            order by b.created desc
+            "##,
+        )
+        .bind(blocker_id)
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)?;
+        Ok(blocks)
+    }
+}
+
+#[Object]
+impl Block {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    /// The blocked user
+    async fn user(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(UserId(self.blocked_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected blocked user {} of block {}",
+                    self.blocked_id, self.id
+                ))
+                .extend()
+            })?
+            .into();
+        Ok(r)
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+/// A user the current user might know, ranked by how connected the two already are
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct FriendSuggestion {
+    pub id: i64,
+    pub handle: String,
+    pub mutual_friends: i64,
+    pub shared_groups: i64,
+    pub in_contacts: bool,
+    pub score: i64,
+}
+
+impl FriendSuggestion {
+    /// Candidates are friends of friends, users in the user's own uploaded contacts,
+    /// and members of the user's groups. Contacts other people uploaded are never used,
+    /// so suggestions don't reveal who has the user's number. Current friends, pending
+    /// requests in either direction and blocked users are left out
+    pub async fn fetch_for_user(
+        pool: &PgPool,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<FriendSuggestion>> {
+        let suggestions = sqlx::query_as(
+            r##"
+            with friends as (
+                select case when f.requestor_id = $1 then f.acceptor_id else f.requestor_id end as friend_id
+                from pin.friends f
+                where f.deleted is false
+                    and f.accepted is not null
+                    and (f.requestor_id = $1 or f.acceptor_id = $1)
+            ), mutual as (
+                select case when f.requestor_id = fr.friend_id then f.acceptor_id else f.requestor_id end as user_id,
+                    count(*) as n
+                from friends fr
+                    inner join pin.friends f
+                        on (f.requestor_id = fr.friend_id or f.acceptor_id = fr.friend_id)
+                where f.deleted is false
+                    and f.accepted is not null
+                group by 1
+            ), contacts as (
+                select c.contact_user_id as user_id
+                from pin.contacts c
+                where c.user_id = $1 and c.deleted is false
+            ), groups as (
+                select other.user_id, count(*) as n
+                from pin.group_associations mine
+                    inner join pin.groups g on g.id = mine.group_id and g.deleted is false
+                    inner join pin.group_associations other
+                        on other.group_id = mine.group_id and other.deleted is false
+                where mine.user_id = $1 and mine.deleted is false
+                group by other.user_id
+            ), candidates as (
+                select user_id from mutual
+                union
+                select user_id from contacts
+                union
+                select user_id from groups
+            )
+            select u.id, u.handle,
+                coalesce(m.n, 0) as mutual_friends,
+                coalesce(g.n, 0) as shared_groups,
+                c.user_id is not null as in_contacts,
+                3 * coalesce(m.n, 0)
+                    + case when c.user_id is not null then 4 else 0 end
+                    + 2 * coalesce(g.n, 0) as score
+            from candidates cand
+                inner join pin.users u on u.id = cand.user_id and u.deleted is false
+                left outer join mutual m on m.user_id = u.id
+                left outer join contacts c on c.user_id = u.id
+                left outer join groups g on g.user_id = u.id
+            where u.id != $1
+                and not exists(
+                    select 1 from pin.friends f
+                    where f.deleted is false
+                        and (
+                            (f.requestor_id = $1 and f.acceptor_id = u.id)
+                            or (f.requestor_id = u.id and f.acceptor_id = $1)
+                        )
+                )
+                and not pin.is_blocked($1, u.id)
+            order by score desc, mutual_friends desc, u.id
+            limit $2
+            "##,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)?;
+        Ok(suggestions)
+    }
+}
+
+#[Object]
+impl FriendSuggestion {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn handle(&self) -> &str {
+        &self.handle
+    }
+    /// The user's human name
+    async fn profile(&self, ctx: &Context<'_>) -> FieldResult<Option<Profile>> {
+        let p = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(ProfileForUserId(self.id))
+            .await?;
+        Ok(p)
+    }
+    /// Number of accepted friends the two users have in common
+    async fn mutual_friends(&self) -> i64 {
+        self.mutual_friends
+    }
+    /// Number of groups both users belong to
+    async fn shared_groups(&self) -> i64 {
+        self.shared_groups
+    }
+    /// Whether the user was found in the current user's uploaded contacts
+    async fn in_contacts(&self) -> bool {
+        self.in_contacts
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct Group {
     pub id: i64,
     pub creating_user_id: i64,
@@ -437,6 +1426,41 @@ pub struct Group {
     pub modified: DateTime<Utc>,
 }
 
+impl Group {
+    /// Fetch a group, locking it so membership changes to the same group
+    /// are applied one at a time
+    pub async fn fetch_for_update(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+    ) -> Result<Option<Group>> {
+        let g = sqlx::query_as(
+            r##"
+            select * from pin.groups
+                where id = $1 and deleted is false
+                for update
+            "##,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(g)
+    }
+
+    pub fn validate_name(name: &str) -> Result<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::BadRequest("group name is required".into()));
+        }
+        if name.chars().count() > 100 {
+            return Err(AppError::BadRequest(
+                "group name must be at most 100 characters".into(),
+            ));
+        }
+        Ok(name.to_string())
+    }
+}
+
 #[Object]
 impl Group {
     async fn id(&self) -> String {
@@ -450,74 +1474,878 @@ impl Group {
             .data_unchecked::<AppLoader>()
             .load_one(UserId(self.creating_user_id))
             .await?
-            .ok_or_else(|| {
-                AppError::E(format!(
-                    "missing expected creating_user_id {} of group {}",
-                    self.creating_user_id, self.id
-                ))
-                .extend()
-            })?
-            .into();
-        Ok(r)
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected creating_user_id {} of group {}",
+                    self.creating_user_id, self.id
+                ))
+                .extend()
+            })?
+            .into();
+        Ok(r)
+    }
+    /// Current members of the group, admins first
+    async fn members(&self, ctx: &Context<'_>) -> FieldResult<Vec<GroupAssociation>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(MembersForGroupId(self.id))
+            .await?
+            .unwrap_or_default();
+        Ok(r)
+    }
+    /// The group's own question of the day, if its admins have submitted any.
+    /// Only visible to members
+    async fn question_of_day(&self, ctx: &Context<'_>) -> FieldResult<Option<Question>> {
+        check_group_member(ctx, self.id).await?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(GroupQuestionOfDay(self.id))
+            .await?;
+        Ok(r)
+    }
+    /// Unused questions in the group's queue in priority order, requires a
+    /// role that can manage the group
+    async fn upcoming_questions(&self, ctx: &Context<'_>) -> FieldResult<Vec<Question>> {
+        let u = ctx.data_opt::<User>().expect("no current user");
+        let pool = ctx.data_unchecked::<PgPool>();
+        let role = GroupRole::fetch_for_member(pool, self.id, u.id)
+            .await
+            .log_error_msg(|| "error fetching group role")
+            .extend()?;
+        if !role.map(|r| r.can_manage).unwrap_or(false) {
+            return Err(AppError::Forbidden("not allowed by group role".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "GROUP_PERMISSION_DENIED")));
+        }
+        let questions = sqlx::query_as(
+            r##"
+            select * from pin.questions
+                where group_id = $1 and deleted is false and used is null
+                order by priority asc, created asc
+            "##,
+        )
+        .bind(self.id)
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "failed querying for upcoming group questions")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(questions)
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+/// A row of `pin.group_roles` and the permissions it grants
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct GroupRole {
+    pub role: String,
+    pub can_invite: bool,
+    pub can_manage: bool,
+}
+
+impl GroupRole {
+    pub const ADMIN: &'static str = "admin";
+    pub const MEMBER: &'static str = "member";
+
+    pub async fn fetch(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        role: &str,
+    ) -> Result<GroupRole> {
+        let r = sqlx::query_as(r##"select * from pin.group_roles where role = $1"##)
+            .bind(role)
+            .fetch_one(&mut *tr)
+            .await
+            .map_err(AppError::from)?;
+        Ok(r)
+    }
+
+    /// The role of a user in a group, `None` if they aren't a member
+    pub async fn fetch_for_member(
+        pool: &PgPool,
+        group_id: i64,
+        user_id: i64,
+    ) -> Result<Option<GroupRole>> {
+        let r = sqlx::query_as(
+            r##"
+            select gr.* from pin.group_roles gr
+                inner join pin.group_associations ga on ga.role = gr.role
+            where ga.group_id = $1 and ga.user_id = $2 and ga.deleted is false
+            "##,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::from)?;
+        Ok(r)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct GroupAssociation {
+    pub id: i64,
+    pub user_id: i64,
+    pub group_id: i64,
+    pub role: String,
+    pub sort_rank: Option<i64>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+impl GroupAssociation {
+    /// The user's current membership in a group
+    pub async fn fetch(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        group_id: i64,
+        user_id: i64,
+    ) -> Result<Option<GroupAssociation>> {
+        let ga = sqlx::query_as(
+            r##"
+            select * from pin.group_associations
+                where group_id = $1 and user_id = $2 and deleted is false
+            "##,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(ga)
+    }
+
+    pub async fn create(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        group_id: i64,
+        user_id: i64,
+        role: &str,
+    ) -> Result<GroupAssociation> {
+        let ga = sqlx::query_as(
+            r##"
+            insert into pin.group_associations
+                (group_id, user_id, role)
+                values ($1, $2, $3)
+                returning *
+            "##,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(ga)
+    }
+
+    pub async fn set_role(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+        role: &str,
+    ) -> Result<GroupAssociation> {
+        let ga = sqlx::query_as(
+            r##"
+            update pin.group_associations
+                set role = $2, modified = now()
+                where id = $1
+                returning *
+            "##,
+        )
+        .bind(id)
+        .bind(role)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(ga)
+    }
+
+    pub async fn delete(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>, id: i64) -> Result<()> {
+        sqlx::query(
+            r##"
+            update pin.group_associations
+                set deleted = true, modified = now()
+                where id = $1
+            "##,
+        )
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(())
+    }
+
+    /// Number of current members of a group holding `role`
+    pub async fn count_with_role(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        group_id: i64,
+        role: &str,
+    ) -> Result<i64> {
+        let (n,): (i64,) = sqlx::query_as(
+            r##"
+            select count(*) from pin.group_associations
+                where group_id = $1 and role = $2 and deleted is false
+            "##,
+        )
+        .bind(group_id)
+        .bind(role)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(n)
+    }
+}
+
+#[Object]
+impl GroupAssociation {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn role(&self) -> &str {
+        &self.role
+    }
+    async fn user(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(UserId(self.user_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected user {} of group association {}",
+                    self.user_id, self.id
+                ))
+                .extend()
+            })?
+            .into();
+        Ok(r)
+    }
+    async fn group(&self, ctx: &Context<'_>) -> FieldResult<Group> {
+        ctx.data_unchecked::<AppLoader>()
+            .load_one(GroupId(self.group_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected group {} of group association {}",
+                    self.group_id, self.id
+                ))
+                .extend()
+            })
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    async fn modified(&self) -> DateTime<Utc> {
+        self.modified
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct GroupInvite {
+    pub id: i64,
+    pub group_id: i64,
+    pub inviter_id: i64,
+    pub invitee_id: i64,
+    pub accepted: Option<DateTime<Utc>>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+impl GroupInvite {
+    /// The user's outstanding invite to a group
+    pub async fn fetch_pending(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        group_id: i64,
+        invitee_id: i64,
+    ) -> Result<Option<GroupInvite>> {
+        let gi = sqlx::query_as(
+            r##"
+            select * from pin.group_invites
+                where group_id = $1
+                    and invitee_id = $2
+                    and accepted is null
+                    and deleted is false
+            "##,
+        )
+        .bind(group_id)
+        .bind(invitee_id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(gi)
+    }
+}
+
+#[Object]
+impl GroupInvite {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn group(&self, ctx: &Context<'_>) -> FieldResult<Group> {
+        ctx.data_unchecked::<AppLoader>()
+            .load_one(GroupId(self.group_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected group {} of group invite {}",
+                    self.group_id, self.id
+                ))
+                .extend()
+            })
+    }
+    /// The member that sent the invite
+    async fn inviter(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(UserId(self.inviter_id))
+            .await?
+            .ok_or_else(|| {
+                AppError::E(format!(
+                    "missing expected inviter {} of group invite {}",
+                    self.inviter_id, self.id
+                ))
+                .extend()
+            })?
+            .into();
+        Ok(r)
+    }
+    async fn accepted(&self) -> Option<DateTime<Utc>> {
+        self.accepted
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
+pub struct Question {
+    pub id: i64,
+    pub kind: String,
+    pub prompt: String,
+    pub used: Option<DateTime<Utc>>,
+    pub priority: i64,
+    pub group_id: Option<i64>,
+    pub scale_min: Option<i64>,
+    pub scale_max: Option<i64>,
+    pub scale_step: Option<i64>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+/// The numbers a scale question can be answered with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ScaleRange {
+    pub min: i64,
+    pub max: i64,
+    #[serde(default = "ScaleRange::default_step")]
+    pub step: i64,
+}
+
+impl ScaleRange {
+    pub const MAX_STEPS: i64 = 100;
+
+    fn default_step() -> i64 {
+        1
+    }
+
+    pub fn validate(self) -> Result<ScaleRange> {
+        // a span that doesn't fit in an i64 is far over MAX_STEPS anyway
+        let span = self.max.checked_sub(self.min);
+        if self.step <= 0
+            || self.min >= self.max
+            || !matches!(span, Some(s) if s % self.step == 0 && s / self.step <= Self::MAX_STEPS)
+        {
+            return Err(AppError::BadRequest(format!(
+                "scales need min < max, with at most {} steps that evenly divide the range",
+                Self::MAX_STEPS
+            )));
+        }
+        Ok(self)
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        value >= self.min && value <= self.max && (value - self.min) % self.step == 0
+    }
+
+    /// Every answerable value, lowest first
+    pub fn values(&self) -> impl Iterator<Item = i64> {
+        (self.min..=self.max).step_by(self.step as usize)
+    }
+}
+
+#[Object]
+impl ScaleRange {
+    async fn min(&self) -> i64 {
+        self.min
+    }
+    async fn max(&self) -> i64 {
+        self.max
+    }
+    async fn step(&self) -> i64 {
+        self.step
+    }
+}
+
+#[test]
+fn test_scale_range_validate() {
+    let range = |min, max, step| ScaleRange { min, max, step }.validate().is_ok();
+    assert!(range(1, 5, 1));
+    assert!(range(-10, 10, 5));
+    assert!(!range(5, 1, 1));
+    assert!(!range(1, 5, 0));
+    assert!(!range(1, 6, 2));
+    assert!(!range(0, 101 * 2, 2));
+    assert!(!range(i64::MIN, i64::MAX, 1));
+    assert!(!range(i64::MIN, 0, i64::MAX));
+}
+
+/// Why a question can't be answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unanswerable {
+    /// Never scheduled as a question of the day
+    Unpublished,
+    /// Only scheduled for days after today
+    Upcoming,
+    /// Last asked before the answering window
+    Closed,
+}
+
+impl Unanswerable {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Unanswerable::Unpublished => "QUESTION_UNPUBLISHED",
+            Unanswerable::Upcoming => "QUESTION_NOT_OPEN",
+            Unanswerable::Closed => "QUESTION_CLOSED",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Unanswerable::Unpublished => "question hasn't been published",
+            Unanswerable::Upcoming => "question isn't open yet",
+            Unanswerable::Closed => "question is closed",
+        }
+    }
+}
+
+/// Whether a question scheduled on `days` can be answered `today`. Each
+/// scheduled question stays the question of the day until the next scheduled
+/// day, so everything from `window_start` (the day of the question that was
+/// current at the start of the window) through today is open
+fn answer_window(
+    days: &[NaiveDate],
+    today: NaiveDate,
+    window_start: Option<NaiveDate>,
+) -> Option<Unanswerable> {
+    // the release image builds with rust 1.69, before `Option::is_none_or`
+    #[allow(clippy::unnecessary_map_or)]
+    let open = |day: &&NaiveDate| **day <= today && window_start.map_or(true, |w| **day >= w);
+    if days.is_empty() {
+        Some(Unanswerable::Unpublished)
+    } else if days.iter().any(|d| open(&d)) {
+        None
+    } else if days.iter().all(|d| *d > today) {
+        Some(Unanswerable::Upcoming)
+    } else {
+        Some(Unanswerable::Closed)
+    }
+}
+
+#[test]
+fn test_answer_window() {
+    let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+    let today = day(16);
+    assert_eq!(
+        answer_window(&[], today, Some(day(16))),
+        Some(Unanswerable::Unpublished)
+    );
+    assert_eq!(answer_window(&[day(16)], today, Some(day(16))), None);
+    assert_eq!(
+        answer_window(&[day(17)], today, Some(day(16))),
+        Some(Unanswerable::Upcoming)
+    );
+    assert_eq!(
+        answer_window(&[day(15)], today, Some(day(16))),
+        Some(Unanswerable::Closed)
+    );
+    // friday's question is still today's on sunday when weekends aren't scheduled
+    assert_eq!(answer_window(&[day(14)], today, Some(day(14))), None);
+    // closed until it's asked again
+    assert_eq!(
+        answer_window(&[day(1), day(17)], today, Some(day(16))),
+        Some(Unanswerable::Closed)
+    );
+    assert_eq!(
+        answer_window(&[day(1), day(16)], today, Some(day(15))),
+        None
+    );
+    // nothing scheduled before the window
+    assert_eq!(answer_window(&[day(10)], today, None), None);
+}
+
+/// Prompt and options for creating or editing a question. Multiple choice
+/// and ranking questions have options, scale questions have a `scale`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct NewQuestion {
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    #[serde(
+        default = "NewQuestion::default_kind",
+        skip_serializing_if = "NewQuestion::is_multi"
+    )]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<ScaleRange>,
+}
+
+impl NewQuestion {
+    pub const MAX_PROMPT_LENGTH: usize = 500;
+    pub const MAX_OPTION_LENGTH: usize = 200;
+    pub const MAX_OPTIONS: usize = 10;
+
+    pub fn multi(prompt: String, options: Vec<String>) -> NewQuestion {
+        NewQuestion {
+            prompt,
+            options,
+            kind: Question::MULTI.to_string(),
+            scale: None,
+        }
+    }
+
+    fn default_kind() -> String {
+        Question::MULTI.to_string()
+    }
+
+    fn is_multi(kind: &str) -> bool {
+        kind == Question::MULTI
+    }
+
+    /// Trim whitespace and check the prompt, and the options or scale the
+    /// question's kind needs, are usable
+    pub fn validate(self) -> Result<NewQuestion> {
+        let prompt = Self::validate_prompt(&self.prompt)?;
+        let mut kind = self.kind.trim().to_lowercase();
+        let (options, scale) = match (kind.as_str(), self.scale) {
+            // yes/no questions are multiple choice questions with fixed options
+            (Question::YES_NO, None) if self.options.is_empty() => {
+                kind = Question::MULTI.to_string();
+                (vec!["Yes".to_string(), "No".to_string()], None)
+            }
+            (Question::MULTI | Question::RANKING, None) => {
+                (Self::validate_options(&self.options)?, None)
+            }
+            (Question::SCALE, Some(scale)) if self.options.is_empty() => {
+                (vec![], Some(scale.validate()?))
+            }
+            (Question::TEXT, None) if self.options.is_empty() => (vec![], None),
+            (
+                Question::MULTI
+                | Question::RANKING
+                | Question::SCALE
+                | Question::TEXT
+                | Question::YES_NO,
+                _,
+            ) => {
+                return Err(AppError::BadRequest(format!(
+                    "{kind} questions need {}",
+                    match kind.as_str() {
+                        Question::SCALE => "a scale and no options",
+                        Question::TEXT | Question::YES_NO => "no options or scale",
+                        _ => "options and no scale",
+                    }
+                )))
+            }
+            _ => {
+                return Err(AppError::BadRequest(format!(
+                    "unknown question kind: {kind}"
+                )))
+            }
+        };
+        Ok(NewQuestion {
+            prompt,
+            options,
+            kind,
+            scale,
+        })
+    }
+
+    pub fn validate_prompt(prompt: &str) -> Result<String> {
+        let prompt = prompt.trim();
+        if prompt.is_empty() || prompt.chars().count() > Self::MAX_PROMPT_LENGTH {
+            return Err(AppError::BadRequest(format!(
+                "prompt must be between 1 and {} characters",
+                Self::MAX_PROMPT_LENGTH
+            )));
+        }
+        Ok(prompt.to_string())
+    }
+
+    pub fn validate_options(options: &[String]) -> Result<Vec<String>> {
+        let options = options
+            .iter()
+            .map(|o| o.trim().to_string())
+            .collect::<Vec<_>>();
+        if options.len() < 2 || options.len() > Self::MAX_OPTIONS {
+            return Err(AppError::BadRequest(format!(
+                "questions need between 2 and {} options",
+                Self::MAX_OPTIONS
+            )));
+        }
+        for (i, opt) in options.iter().enumerate() {
+            if opt.is_empty() || opt.chars().count() > Self::MAX_OPTION_LENGTH {
+                return Err(AppError::BadRequest(format!(
+                    "options must be between 1 and {} characters",
+                    Self::MAX_OPTION_LENGTH
+                )));
+            }
+            if options[..i].iter().any(|o| o.eq_ignore_ascii_case(opt)) {
+                return Err(AppError::BadRequest(format!("duplicate option: {opt}")));
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl Question {
+    pub const MULTI: &'static str = "multi";
+    pub const SCALE: &'static str = "scale";
+    pub const TEXT: &'static str = "text";
+    pub const RANKING: &'static str = "ranking";
+    /// Not stored, creates a multiple choice question with yes and no options
+    pub const YES_NO: &'static str = "yesno";
+
+    pub const MAX_TEXT_ANSWER_LENGTH: usize = 500;
+
+    /// Whether the question is answered using its options
+    pub fn has_options(&self) -> bool {
+        self.kind == Self::MULTI || self.kind == Self::RANKING
+    }
+
+    pub fn scale_range(&self) -> Option<ScaleRange> {
+        match (self.scale_min, self.scale_max, self.scale_step) {
+            (Some(min), Some(max), Some(step)) => Some(ScaleRange { min, max, step }),
+            _ => None,
+        }
+    }
+
+    /// Check an answer fits this question's kind, returning it normalized
+    pub async fn validate_answer(
+        &self,
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        answer: Answer,
+    ) -> Result<Answer> {
+        let invalid = |msg: String| Err(AppError::BadRequest(msg));
+        match (self.kind.as_str(), answer) {
+            (Self::MULTI, Answer::Multi(id)) => Ok(Answer::Multi(id)),
+            (Self::SCALE, Answer::Scale(value)) => match self.scale_range() {
+                Some(scale) if scale.contains(value) => Ok(Answer::Scale(value)),
+                Some(scale) => invalid(format!(
+                    "answer must be between {} and {} in steps of {}",
+                    scale.min, scale.max, scale.step
+                )),
+                None => Err(AppError::from(format!(
+                    "scale question {} has no scale",
+                    self.id
+                ))),
+            },
+            (Self::TEXT, Answer::Text(text)) => {
+                let text = text.trim();
+                if text.is_empty() || text.chars().count() > Self::MAX_TEXT_ANSWER_LENGTH {
+                    return invalid(format!(
+                        "answer must be between 1 and {} characters",
+                        Self::MAX_TEXT_ANSWER_LENGTH
+                    ));
+                }
+                Ok(Answer::Text(text.to_string()))
+            }
+            (Self::RANKING, Answer::Ranking(ranking)) => {
+                let mut options = Self::get_options(self.id, tr)
+                    .await?
+                    .into_iter()
+                    .map(|o| o.id)
+                    .collect::<Vec<_>>();
+                let mut sorted = ranking.clone();
+                options.sort_unstable();
+                sorted.sort_unstable();
+                if sorted != options {
+                    return invalid("rankings must order every option exactly once".into());
+                }
+                Ok(Answer::Ranking(ranking))
+            }
+            (kind, _) => invalid(format!("wrong kind of answer for a {kind} question")),
+        }
+    }
+
+    /// Whether `option_id` is one of the question's current options
+    pub async fn has_option(
+        &self,
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        option_id: i64,
+    ) -> Result<bool> {
+        Ok(Self::get_options(self.id, tr)
+            .await?
+            .iter()
+            .any(|o| o.id == option_id))
     }
-}
 
-#[derive(Debug, Clone, sqlx::FromRow)]
-pub struct GroupAssociation {
-    pub id: i64,
-    pub user_id: i64,
-    pub group_id: i64,
-    pub role: String,
-    pub sort_rank: i64,
-    pub deleted: bool,
-    pub created: DateTime<Utc>,
-    pub modified: DateTime<Utc>,
-}
+    /// Check the question is, or was within the last `ANSWER_WINDOW_DAYS`, a
+    /// question of the day for someone in `timezone`. Group questions roll over
+    /// on the server's day like `group.questionOfDay`
+    pub async fn check_answerable(
+        &self,
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        timezone: Option<&str>,
+    ) -> Result<Option<Unanswerable>> {
+        let timezone = if self.group_id.is_some() {
+            None
+        } else {
+            timezone
+        };
+        let (today, days, window_start): (NaiveDate, Vec<NaiveDate>, Option<NaiveDate>) =
+            sqlx::query_as(
+                r##"
+                with local as (
+                    select timezone(coalesce($3, 'America/New_York'), now())::date as today
+                )
+                select l.today,
+                    array(
+                        select s.day from pin.question_schedule s
+                        where s.question_id = $1 and s.deleted is false
+                        order by s.day
+                    ) as days,
+                    (
+                        select max(s.day) from pin.question_schedule s
+                        where s.deleted is false
+                            and s.group_id is not distinct from $2
+                            and s.day <= l.today - $4::int
+                    ) as window_start
+                from local l
+                "##,
+            )
+            .bind(self.id)
+            .bind(self.group_id)
+            .bind(timezone)
+            .bind(crate::CONFIG.answer_window_days as i32)
+            .fetch_one(&mut *tr)
+            .await
+            .map_err(AppError::from)?;
+        Ok(answer_window(&days, today, window_start))
+    }
 
-#[Object]
-impl GroupAssociation {
-    async fn id(&self) -> String {
-        self.id.to_string()
+    /// Group questions and their responses are only visible to the group's members
+    pub async fn check_visible(&self, ctx: &Context<'_>) -> FieldResult<()> {
+        match self.group_id {
+            Some(group_id) => check_group_member(ctx, group_id).await,
+            None => Ok(()),
+        }
     }
-    async fn role(&self) -> &str {
-        &self.role
+
+    pub async fn fetch(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+    ) -> Result<Option<Question>> {
+        let question =
+            sqlx::query_as(r##"select * from pin.questions where id = $1 and deleted is false"##)
+                .bind(id)
+                .fetch_optional(&mut *tr)
+                .await
+                .map_err(AppError::from)?;
+        Ok(question)
     }
-    async fn user(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
-        let r = ctx
-            .data_unchecked::<AppLoader>()
-            .load_one(UserId(self.user_id))
-            .await?
-            .ok_or_else(|| {
-                AppError::E(format!(
-                    "missing expected user {} of group association {}",
-                    self.user_id, self.id
-                ))
-                .extend()
-            })?
-            .into();
-        Ok(r)
+
+    /// Insert a validated question and its options. Questions
+    /// go to the back of the queue unless a `priority` is given. Questions with
+    /// a `group_id` go in that group's queue instead of the global one
+    pub async fn create(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        question: &NewQuestion,
+        priority: Option<i64>,
+        group_id: Option<i64>,
+    ) -> Result<Question> {
+        let created: Question = sqlx::query_as(
+            r##"
+            insert into pin.questions
+                (kind, prompt, priority, group_id, scale_min, scale_max, scale_step)
+                values ($1, $2, coalesce($3, nextval('pin.question_priority_seq')), $4, $5, $6, $7)
+                returning *
+            "##,
+        )
+        .bind(&question.kind)
+        .bind(&question.prompt)
+        .bind(priority)
+        .bind(group_id)
+        .bind(question.scale.map(|s| s.min))
+        .bind(question.scale.map(|s| s.max))
+        .bind(question.scale.map(|s| s.step))
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        if !question.options.is_empty() {
+            Self::insert_options(tr, created.id, &question.options).await?;
+        }
+        Ok(created)
     }
-    async fn created(&self) -> DateTime<Utc> {
-        self.created
+
+    /// Replace all of a question's options
+    pub async fn replace_options(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+        options: &[String],
+    ) -> Result<()> {
+        sqlx::query(
+            r##"
+            update pin.question_multi_options
+                set deleted = true, modified = now()
+                where question_id = $1 and deleted is false
+            "##,
+        )
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Self::insert_options(tr, id, options).await
     }
-    async fn modified(&self) -> DateTime<Utc> {
-        self.modified
+
+    async fn insert_options(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+        options: &[String],
+    ) -> Result<()> {
+        let ranks = (0..options.len() as i64).collect::<Vec<_>>();
+        sqlx::query(
+            r##"
+            insert into pin.question_multi_options (question_id, rank, value)
+                select $1, rank, value from unnest($2::bigint[], $3::text[]) as t(rank, value)
+            "##,
+        )
+        .bind(id)
+        .bind(&ranks)
+        .bind(options)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(())
     }
-}
 
-#[derive(Debug, Clone, sqlx::FromRow)]
-pub struct Question {
-    pub id: i64,
-    pub kind: String,
-    pub prompt: String,
-    pub used: Option<DateTime<Utc>>,
-    pub priority: i64,
-    pub deleted: bool,
-    pub created: DateTime<Utc>,
-    pub modified: DateTime<Utc>,
-}
+    pub async fn has_pinions(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        id: i64,
+    ) -> Result<bool> {
+        let (exists,): (bool,) =
+            sqlx::query_as(r##"select exists(select 1 from pin.pinions where question_id = $1)"##)
+                .bind(id)
+                .fetch_one(&mut *tr)
+                .await
+                .map_err(AppError::from)?;
+        Ok(exists)
+    }
 
-impl Question {
     pub async fn mark_used(
         id: i64,
         tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
@@ -548,31 +2376,148 @@ impl Question {
     }
 }
 
+/// A day on the question of the day calendar
 #[derive(Debug, Clone, sqlx::FromRow)]
-pub struct QuestionOptionCount {
-    pub multi_selection: i64,
-    pub count: i64,
+#[allow(dead_code)]
+pub struct ScheduledQuestion {
+    pub id: i64,
+    pub day: NaiveDate,
+    pub question_id: i64,
+    pub auto: bool,
+    pub group_id: Option<i64>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
 }
 
-impl QuestionOptionCount {
-    pub async fn get_option_counts(
+impl ScheduledQuestion {
+    /// Days on the global calendar, or a group's calendar when `group_id` is given
+    pub async fn fetch_range(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        group_id: Option<i64>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<ScheduledQuestion>> {
+        let days = sqlx::query_as(
+            r##"
+            select * from pin.question_schedule
+                where day >= $1 and day <= $2
+                    and group_id is not distinct from $3
+                    and deleted is false
+                order by day
+            "##,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(group_id)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(days)
+    }
+
+    pub async fn create(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        group_id: Option<i64>,
+        day: NaiveDate,
         question_id: i64,
+        auto: bool,
+    ) -> Result<ScheduledQuestion> {
+        let s = sqlx::query_as(
+            r##"
+            insert into pin.question_schedule (day, question_id, auto, group_id)
+                values ($1, $2, $3, $4)
+                returning *
+            "##,
+        )
+        .bind(day)
+        .bind(question_id)
+        .bind(auto)
+        .bind(group_id)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(s)
+    }
+
+    pub async fn set_question(
         tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    ) -> Result<Vec<Self>> {
-        let counts: Vec<Self> = sqlx::query_as(
+        id: i64,
+        question_id: i64,
+    ) -> Result<()> {
+        sqlx::query(
             r##"
-            select multi_selection, count(*)
-            from pin.pinions
-            where question_id = $1 and deleted is false
-            group by multi_selection
+            update pin.question_schedule
+                set question_id = $2, modified = now()
+                where id = $1
             "##,
         )
+        .bind(id)
         .bind(question_id)
-        .fetch_all(&mut *tr)
+        .execute(&mut *tr)
         .await
         .map_err(AppError::from)?;
-        Ok(counts)
+        Ok(())
+    }
+
+    pub async fn delete(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>, id: i64) -> Result<()> {
+        sqlx::query(
+            r##"
+            update pin.question_schedule
+                set deleted = true, modified = now()
+                where id = $1
+            "##,
+        )
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(())
+    }
+}
+
+#[Object]
+impl ScheduledQuestion {
+    async fn day(&self) -> NaiveDate {
+        self.day
+    }
+
+    /// Whether this day was filled in by priority rather than scheduled by an admin.
+    /// Automatic days can change until they arrive
+    async fn auto(&self) -> bool {
+        self.auto
+    }
+
+    async fn question(&self, ctx: &Context<'_>) -> FieldResult<Option<Question>> {
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(QuestionId(self.question_id))
+            .await?;
+        Ok(r)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct QuestionOptionCount {
+    /// Null for answers to questions that aren't multiple choice
+    pub multi_selection: Option<i64>,
+    pub count: i64,
+}
+
+impl QuestionOptionCount {
+    /// Pair each option with its count, in option order. Counts for anything
+    /// that isn't one of the options are dropped
+    pub fn by_option(options: &[i64], counts: &[Self]) -> Vec<(i64, i64)> {
+        let counts = counts
+            .iter()
+            .filter_map(|c| Some((c.multi_selection?, c.count)))
+            .collect::<HashMap<i64, i64>>();
+        options
+            .iter()
+            .map(|id| (*id, counts.get(id).copied().unwrap_or(0)))
+            .collect()
     }
+
     pub async fn get_option_counts_friends(
         question_id: i64,
         user_id: i64,
@@ -589,6 +2534,7 @@ impl QuestionOptionCount {
                  p.question_id = $1
                  and (f.requestor_id = $2 or f.acceptor_id = $2)
                  and p.deleted is false
+                 and p.multi_selection is not null
                  and f.deleted is false
             group by p.multi_selection
             "##,
@@ -602,6 +2548,30 @@ impl QuestionOptionCount {
     }
 }
 
+#[test]
+fn test_option_counts_skip_other_answers() {
+    let counts = [
+        QuestionOptionCount {
+            multi_selection: Some(2),
+            count: 3,
+        },
+        // friends' scale, text and ranking answers
+        QuestionOptionCount {
+            multi_selection: None,
+            count: 4,
+        },
+        QuestionOptionCount {
+            multi_selection: Some(9),
+            count: 1,
+        },
+    ];
+    assert_eq!(
+        QuestionOptionCount::by_option(&[1, 2], &counts),
+        vec![(1, 0), (2, 3)]
+    );
+    assert!(QuestionOptionCount::by_option(&[], &counts).is_empty());
+}
+
 #[Object]
 impl Question {
     async fn id(&self) -> String {
@@ -616,6 +2586,29 @@ impl Question {
         self.prompt.clone()
     }
 
+    /// Position in the queue of upcoming questions, lower is sooner
+    async fn priority(&self) -> i64 {
+        self.priority
+    }
+
+    /// When this question was first the question of the day
+    async fn used(&self) -> Option<DateTime<Utc>> {
+        self.used
+    }
+
+    /// The group whose members this question is asked to, for group questions
+    async fn group(&self, ctx: &Context<'_>) -> FieldResult<Option<Group>> {
+        let group_id = match self.group_id {
+            Some(group_id) => group_id,
+            None => return Ok(None),
+        };
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(GroupId(group_id))
+            .await?;
+        Ok(r)
+    }
+
     /// The current user's response to this question
     async fn pinion(&self, ctx: &Context<'_>) -> FieldResult<Option<Pinion>> {
         let u = ctx.data_opt::<User>().expect("no current user");
@@ -626,9 +2619,14 @@ impl Question {
         Ok(r)
     }
 
-    /// List of answer options
+    /// The range of numbers scale questions are answered with
+    async fn scale(&self) -> Option<ScaleRange> {
+        self.scale_range()
+    }
+
+    /// List of answer options, for multiple choice and ranking questions
     async fn options(&self, ctx: &Context<'_>) -> FieldResult<Option<Vec<QuestionMultiOption>>> {
-        if self.kind != "multi" {
+        if !self.has_options() {
             Ok(None)
         } else {
             let r = ctx
@@ -642,6 +2640,7 @@ impl Question {
 
     /// A summary of responses (counts and percentages)
     async fn summary(&self, ctx: &Context<'_>) -> FieldResult<QuestionSummary> {
+        self.check_visible(ctx).await?;
         let pool = ctx.data_unchecked::<PgPool>();
         question_summary(self.id, pool)
             .await
@@ -651,6 +2650,7 @@ impl Question {
 
     /// A summary of friends responses (counts and percentages)
     async fn friend_summary(&self, ctx: &Context<'_>) -> FieldResult<QuestionSummary> {
+        self.check_visible(ctx).await?;
         let u = ctx.data_opt::<User>().expect("no current user");
         let pool = ctx.data_unchecked::<PgPool>();
         question_friends_summary(self.id, u.id, pool)
@@ -661,6 +2661,7 @@ impl Question {
 
     /// Load pinions of friends for this question
     async fn friend_pinions(&self, ctx: &Context<'_>) -> FieldResult<Vec<FriendPinion>> {
+        self.check_visible(ctx).await?;
         let u = ctx.data_opt::<User>().expect("no current user");
         ctx.data_unchecked::<AppLoader>()
             .load_one(PinionsOfFriendsForUserQuestionId(u.id, self.id))
@@ -674,6 +2675,68 @@ impl Question {
             })
             .extend()
     }
+
+    /// A summary of the responses of a group's members, the current user must
+    /// be a member of the group
+    async fn group_summary(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+    ) -> FieldResult<QuestionSummary> {
+        let group_id = group_id.parse::<i64>()?;
+        self.check_visible(ctx).await?;
+        check_group_member(ctx, group_id).await?;
+        ctx.data_unchecked::<AppLoader>()
+            .load_one(GroupSummaryForQuestionId(group_id, self.id))
+            .await?
+            .ok_or_else(|| {
+                AppError::from(format!(
+                    "unable to load summary of group {}, q {}",
+                    group_id, self.id
+                ))
+            })
+            .extend()
+    }
+
+    /// Load pinions of a group's members for this question, the current user
+    /// must be a member of the group
+    async fn group_pinions(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+    ) -> FieldResult<Vec<GroupPinion>> {
+        let group_id = group_id.parse::<i64>()?;
+        self.check_visible(ctx).await?;
+        check_group_member(ctx, group_id).await?;
+        let r = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(PinionsOfGroupForQuestionId(group_id, self.id))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(GroupPinion::from)
+            .collect();
+        Ok(r)
+    }
+}
+
+/// Errors unless the current user is a member of the group. Groups the user
+/// isn't in are reported the same as groups that don't exist
+async fn check_group_member(ctx: &Context<'_>, group_id: i64) -> FieldResult<()> {
+    let u = ctx.data_opt::<User>().expect("no current user");
+    let is_member = ctx
+        .data_unchecked::<AppLoader>()
+        .load_one(GroupAssociationsForUserId(u.id))
+        .await?
+        .unwrap_or_default()
+        .iter()
+        .any(|ga| ga.group_id == group_id);
+    if !is_member {
+        return Err(AppError::BadRequest(format!("unknown group {group_id}"))
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "UNKNOWN_GROUP")));
+    }
+    Ok(())
 }
 
 use cached::proc_macro::cached;
@@ -686,8 +2749,60 @@ use cached::TimedSizedCache;
     create = "{ TimedSizedCache::with_size_and_lifespan(10, 10) }",
     convert = r#"{ id }"#
 )]
-async fn question_summary(id: i64, pool: &PgPool) -> Result<QuestionSummary> {
+pub async fn question_summary(id: i64, pool: &PgPool) -> Result<QuestionSummary> {
     tracing::info!("loading question summary for question {}", id);
+    let mut summary = load_question_summary(id, pool).await?;
+    let (switched,): (i64,) = sqlx::query_as(
+        r##"
+        select count(*) from pin.pinions cur
+            inner join lateral (
+                select * from pin.pinions f
+                where f.user_id = cur.user_id and f.question_id = cur.question_id
+                order by f.created, f.id
+                limit 1
+            ) first on true
+        where cur.question_id = $1
+            and cur.deleted is false
+            and (first.multi_selection, first.scale_value, first.text_value, first.ranking)
+                is distinct from
+                (cur.multi_selection, cur.scale_value, cur.text_value, cur.ranking)
+        "##,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error counting switched answers")?;
+    summary.switched_count = Some(switched);
+    Ok(summary)
+}
+
+async fn load_question_summary(id: i64, pool: &PgPool) -> Result<QuestionSummary> {
+    let question: Option<Question> = sqlx::query_as("select * from pin.questions where id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error loading summary question")?;
+    match question {
+        Some(q) if q.kind == Question::SCALE => return scale_question_summary(&q, pool).await,
+        Some(q) if q.kind == Question::RANKING => return ranking_question_summary(&q, pool).await,
+        Some(q) if q.kind == Question::TEXT => {
+            let (total_count,): (i64,) = sqlx::query_as(
+                "select count(*) from pin.pinions where question_id = $1 and deleted is false",
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error counting text answers")?;
+            return Ok(QuestionSummary {
+                total_count,
+                ..QuestionSummary::default()
+            });
+        }
+        _ => (),
+    }
     let query = r##"
         select * from pin.question_multi_option_tallies
             where
@@ -703,10 +2818,7 @@ async fn question_summary(id: i64, pool: &PgPool) -> Result<QuestionSummary> {
         .log_error_msg(|| "error loading question multi option tallies")?;
     tracing::info!("loaded {} question multi option tallies", res.len());
     if res.is_empty() {
-        return Ok(QuestionSummary {
-            total_count: 0,
-            options: vec![],
-        });
+        return Ok(QuestionSummary::default());
     }
     let total_count = res.iter().map(|ot| ot.count).sum();
     let options = res
@@ -716,9 +2828,77 @@ async fn question_summary(id: i64, pool: &PgPool) -> Result<QuestionSummary> {
     Ok(QuestionSummary {
         total_count,
         options,
+        ..QuestionSummary::default()
+    })
+}
+
+/// Scale answers aren't tallied, they're counted when the summary is loaded
+async fn scale_question_summary(question: &Question, pool: &PgPool) -> Result<QuestionSummary> {
+    let scale = question
+        .scale_range()
+        .ok_or_else(|| AppError::from(format!("scale question {} has no scale", question.id)))?;
+    let counts: Vec<(i64, i64)> = sqlx::query_as(
+        r##"
+        select scale_value, count(*) from pin.pinions
+            where question_id = $1 and deleted is false and scale_value is not null
+            group by scale_value
+        "##,
+    )
+    .bind(question.id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error counting scale answers")?;
+    let scale = ScaleSummary::from_counts(scale, &counts.into_iter().collect());
+    Ok(QuestionSummary {
+        total_count: scale.total_count,
+        scale: Some(scale),
+        ..QuestionSummary::default()
+    })
+}
+
+async fn ranking_question_summary(question: &Question, pool: &PgPool) -> Result<QuestionSummary> {
+    let mut tr = pool.begin().await.map_err(AppError::from)?;
+    let options = Question::get_options(question.id, &mut tr)
+        .await?
+        .into_iter()
+        .map(|o| o.id)
+        .collect::<Vec<_>>();
+    let ballots: Vec<(Vec<i64>,)> = sqlx::query_as(
+        r##"
+        select ranking from pin.pinions
+            where question_id = $1 and deleted is false and ranking is not null
+        "##,
+    )
+    .bind(question.id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error loading rankings")?;
+    tr.commit().await.map_err(AppError::from)?;
+    let ballots = ballots.into_iter().map(|(b,)| b).collect::<Vec<_>>();
+    Ok(QuestionSummary {
+        total_count: ballots.len() as i64,
+        ranking: Some(RankingSummary::from_ballots(&options, &ballots)),
+        ..QuestionSummary::default()
     })
 }
 
+/// Drop cached summaries of a question after its tallies change
+pub async fn forget_question_summary(id: i64) {
+    use cached::Cached;
+    QUESTION_SUMMARY.lock().await.cache_remove(&id);
+    let mut friends = QUESTION_FRIENDS_SUMMARY.lock().await;
+    let stale = friends
+        .key_order()
+        .filter(|(question_id, _)| *question_id == id)
+        .copied()
+        .collect::<Vec<_>>();
+    for key in stale {
+        friends.cache_remove(&key);
+    }
+}
+
 #[cached(
     result = true,
     sync_writes = true,
@@ -743,40 +2923,138 @@ async fn question_friends_summary(id: i64, user_id: i64, pool: &PgPool) -> Resul
     let option_counts = QuestionOptionCount::get_option_counts_friends(id, user_id, &mut tr)
         .await
         .log_error_msg(|| "friends: error getting option counts")?;
-    let option_counts = option_counts
-        .into_iter()
-        .map(|count| (count.multi_selection, count.count))
-        .collect::<HashMap<i64, i64>>();
-
-    let mut total_count = 0;
-    let mut option_tallies = vec![];
-    for opt in options.into_iter() {
-        let count = option_counts.get(&opt.id).unwrap_or(&0);
-        total_count += count;
-        option_tallies.push(QuestionMultiOptionTally {
-            id: 0,
-            question_id: id,
-            multi_selection: opt.id,
-            count: *count,
-            deleted: false,
-        });
-    }
-    let option_summaries = option_tallies
-        .into_iter()
-        .map(|opt| opt.to_option_summary(total_count))
-        .collect::<Vec<_>>();
+    let options = options.into_iter().map(|opt| opt.id).collect::<Vec<_>>();
+    let res = QuestionSummary::from_option_counts(
+        id,
+        QuestionOptionCount::by_option(&options, &option_counts),
+    );
     tr.commit()
         .await
         .map_err(AppError::from)
         .log_error_msg(|| "friends: error committing changes")?;
-    let res = QuestionSummary {
-        total_count,
-        options: option_summaries,
-    };
     Ok(res)
 }
 
+/// How often two friends answered the same questions the same way
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
+pub struct Agreement {
+    pub friend_id: i64,
+    pub shared: i64,
+    pub agreed: i64,
+    pub recent_shared: i64,
+    pub recent_agreed: i64,
+}
+
+impl Agreement {
+    /// Agreement over the last 30 days when `recent`, otherwise all time
+    pub fn window(&self, recent: bool) -> AgreementWindow {
+        if recent {
+            AgreementWindow {
+                shared: self.recent_shared,
+                agreed: self.recent_agreed,
+            }
+        } else {
+            AgreementWindow {
+                shared: self.shared,
+                agreed: self.agreed,
+            }
+        }
+    }
+}
+
+#[Object]
+impl Agreement {
+    /// Questions answered in the last 30 days
+    async fn last_30_days(&self) -> AgreementWindow {
+        self.window(true)
+    }
+    async fn all_time(&self) -> AgreementWindow {
+        self.window(false)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AgreementWindow {
+    pub shared: i64,
+    pub agreed: i64,
+}
+
+impl AgreementWindow {
+    /// Fraction of shared questions with the same answer
+    pub fn agreed_fraction(&self) -> Option<f64> {
+        (self.shared > 0).then(|| self.agreed as f64 / self.shared as f64)
+    }
+}
+
+#[Object]
+impl AgreementWindow {
+    /// Questions both users answered
+    async fn shared_questions(&self) -> i64 {
+        self.shared
+    }
+    /// Shared questions both users gave the same answer to
+    async fn same_answers(&self) -> i64 {
+        self.agreed
+    }
+    /// Percentage of shared questions with the same answer, null when there are none
+    async fn percentage(&self) -> Option<i64> {
+        self.agreed_fraction().map(|f| (f * 100.).round() as i64)
+    }
+}
+
+/// Agreement between a user and each of their accepted friends, computed in one
+/// pass over both users' multiple choice pinions. A question counts towards the last 30 days by the
+/// day it was the question of the day, or by the later answer if it never was
+#[cached(
+    result = true,
+    sync_writes = true,
+    type = "TimedSizedCache<i64, HashMap<i64, Agreement>>",
+    create = "{ TimedSizedCache::with_size_and_lifespan(1000, 300) }",
+    convert = r#"{ user_id }"#
+)]
+pub async fn friend_agreements(user_id: i64, pool: &PgPool) -> Result<HashMap<i64, Agreement>> {
+    let agreements: Vec<Agreement> = sqlx::query_as(
+        r##"
+        with friends as (
+            select case when f.requestor_id = $1 then f.acceptor_id else f.requestor_id end as friend_id
+            from pin.friends f
+            where f.deleted is false
+                and f.accepted is not null
+                and (f.requestor_id = $1 or f.acceptor_id = $1)
+        ), shared as (
+            select theirs.user_id as friend_id,
+                mine.multi_selection = theirs.multi_selection as agreed,
+                coalesce(q.used, greatest(mine.created, theirs.created))
+                    > now() - interval '30 days' as recent
+            from pin.pinions mine
+                inner join pin.pinions theirs
+                    on theirs.question_id = mine.question_id and theirs.deleted is false
+                inner join friends fr on fr.friend_id = theirs.user_id
+                inner join pin.questions q on q.id = mine.question_id
+            where mine.user_id = $1
+                and mine.deleted is false
+                and mine.multi_selection is not null
+        )
+        select fr.friend_id,
+            count(s.friend_id) as shared,
+            count(s.friend_id) filter (where s.agreed) as agreed,
+            count(s.friend_id) filter (where s.recent) as recent_shared,
+            count(s.friend_id) filter (where s.recent and s.agreed) as recent_agreed
+        from friends fr
+            left outer join shared s on s.friend_id = fr.friend_id
+        group by fr.friend_id
+        "##,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error loading friend agreements")?;
+    Ok(agreements.into_iter().map(|a| (a.friend_id, a)).collect())
+}
+
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct QuestionMultiOptionTally {
     pub id: i64,
     pub question_id: i64,
@@ -790,15 +3068,51 @@ impl QuestionMultiOptionTally {
         OptionSummary {
             option_id: self.multi_selection,
             count: self.count,
-            percentage: (self.count as f64 / total_answer_count as f64 * 100.).round() as i64,
+            percentage: percentage(self.count, total_answer_count),
         }
     }
 }
 
-#[derive(Clone)]
+/// Responses to a question. Multiple choice questions fill in `options`,
+/// scale and ranking questions have their own breakdown. Friend and group
+/// summaries only break down multiple choice questions
+#[derive(Clone, Default)]
 pub struct QuestionSummary {
     pub total_count: i64,
     pub options: Vec<OptionSummary>,
+    pub scale: Option<ScaleSummary>,
+    pub ranking: Option<RankingSummary>,
+    /// Only counted for summaries of everyone's responses
+    pub switched_count: Option<i64>,
+}
+
+impl QuestionSummary {
+    /// Summarize `(option id, count)` pairs, keeping their order
+    pub fn from_option_counts(
+        question_id: i64,
+        counts: impl IntoIterator<Item = (i64, i64)>,
+    ) -> Self {
+        let tallies = counts
+            .into_iter()
+            .map(|(multi_selection, count)| QuestionMultiOptionTally {
+                id: 0,
+                question_id,
+                multi_selection,
+                count,
+                deleted: false,
+            })
+            .collect::<Vec<_>>();
+        let total_count = tallies.iter().map(|t| t.count).sum();
+        let options = tallies
+            .into_iter()
+            .map(|t| t.to_option_summary(total_count))
+            .collect();
+        Self {
+            total_count,
+            options,
+            ..Self::default()
+        }
+    }
 }
 
 #[Object]
@@ -809,9 +3123,317 @@ impl QuestionSummary {
     async fn options(&self) -> &[OptionSummary] {
         &self.options
     }
+    /// Histogram of answers to a scale question
+    async fn scale(&self) -> Option<&ScaleSummary> {
+        self.scale.as_ref()
+    }
+    /// Borda count and instant-runoff results of a ranking question
+    async fn ranking(&self) -> Option<&RankingSummary> {
+        self.ranking.as_ref()
+    }
+    /// How many people changed their answer since they first answered.
+    /// Null for friend and group summaries
+    async fn switched_count(&self) -> Option<i64> {
+        self.switched_count
+    }
 }
 
-#[derive(Clone)]
+fn percentage(count: i64, total: i64) -> i64 {
+    if total == 0 {
+        0
+    } else {
+        (count as f64 / total as f64 * 100.).round() as i64
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScaleSummary {
+    pub total_count: i64,
+    /// Every value on the scale, lowest first, including ones nobody chose
+    pub buckets: Vec<ScaleBucket>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScaleBucket {
+    pub value: i64,
+    pub count: i64,
+    pub percentage: i64,
+}
+
+impl ScaleSummary {
+    /// Summarize how many times each value was chosen
+    pub fn from_counts(scale: ScaleRange, counts: &HashMap<i64, i64>) -> Self {
+        let mut values = counts
+            .iter()
+            .filter(|(v, c)| scale.contains(**v) && **c > 0)
+            .map(|(v, c)| (*v, *c))
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        let total_count: i64 = values.iter().map(|(_, c)| c).sum();
+        let buckets = scale
+            .values()
+            .map(|value| {
+                let count = counts.get(&value).copied().unwrap_or(0);
+                ScaleBucket {
+                    value,
+                    count,
+                    percentage: percentage(count, total_count),
+                }
+            })
+            .collect();
+        let mean = (total_count > 0).then(|| {
+            values
+                .iter()
+                .map(|(v, c)| *v as f64 * *c as f64)
+                .sum::<f64>()
+                / total_count as f64
+        });
+        // the value at a 0-based position in the sorted answers
+        let nth = |n: i64| {
+            let mut seen = 0;
+            values
+                .iter()
+                .find(|(_, c)| {
+                    seen += c;
+                    seen > n
+                })
+                .map(|(v, _)| *v as f64)
+        };
+        let median = match total_count {
+            0 => None,
+            n if n % 2 == 1 => nth(n / 2),
+            n => nth(n / 2 - 1).zip(nth(n / 2)).map(|(a, b)| (a + b) / 2.),
+        };
+        ScaleSummary {
+            total_count,
+            buckets,
+            mean,
+            median,
+        }
+    }
+}
+
+#[Object]
+impl ScaleSummary {
+    async fn buckets(&self) -> &[ScaleBucket] {
+        &self.buckets
+    }
+    async fn mean(&self) -> Option<f64> {
+        self.mean
+    }
+    async fn median(&self) -> Option<f64> {
+        self.median
+    }
+}
+
+#[Object]
+impl ScaleBucket {
+    async fn value(&self) -> i64 {
+        self.value
+    }
+    async fn count(&self) -> i64 {
+        self.count
+    }
+    /// Percentage of responses that chose this value
+    async fn percentage(&self) -> i64 {
+        self.percentage
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankingSummary {
+    /// Options by Borda count, most points first. An option ranked first on a
+    /// ballot gets one point for each option below it, the last option gets none
+    pub borda: Vec<BordaScore>,
+    /// Instant-runoff rounds, until an option has a majority of first choices
+    pub rounds: Vec<RunoffRound>,
+    pub winner: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BordaScore {
+    pub option_id: i64,
+    pub points: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunoffRound {
+    /// First choice counts of the options still standing, most votes first
+    pub options: Vec<OptionSummary>,
+    pub eliminated: Option<i64>,
+}
+
+impl RankingSummary {
+    /// Count `ballots` (option ids, most preferred first). Runoff ties for last
+    /// place eliminate the option with fewer Borda points, then the higher id
+    pub fn from_ballots(options: &[i64], ballots: &[Vec<i64>]) -> Self {
+        let n = options.len() as i64;
+        let ballots = ballots
+            .iter()
+            .map(|b| {
+                b.iter()
+                    .filter(|id| options.contains(id))
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|b| !b.is_empty())
+            .collect::<Vec<_>>();
+
+        let mut points = options
+            .iter()
+            .map(|id| (*id, 0))
+            .collect::<HashMap<i64, i64>>();
+        for ballot in &ballots {
+            for (i, id) in ballot.iter().enumerate() {
+                *points.entry(*id).or_default() += n - 1 - i as i64;
+            }
+        }
+        let mut borda = points
+            .iter()
+            .map(|(option_id, points)| BordaScore {
+                option_id: *option_id,
+                points: *points,
+            })
+            .collect::<Vec<_>>();
+        borda.sort_by_key(|b| (-b.points, b.option_id));
+
+        let mut rounds = vec![];
+        let mut winner = None;
+        let mut standing = options.to_vec();
+        while !ballots.is_empty() && !standing.is_empty() {
+            let mut firsts = standing
+                .iter()
+                .map(|id| (*id, 0))
+                .collect::<HashMap<i64, i64>>();
+            let mut counted = 0;
+            for ballot in &ballots {
+                if let Some(id) = ballot.iter().find(|id| standing.contains(id)) {
+                    *firsts.entry(*id).or_default() += 1;
+                    counted += 1;
+                }
+            }
+            let mut counts = firsts.into_iter().collect::<Vec<_>>();
+            counts.sort_by_key(|(id, count)| (-count, -points[id], *id));
+            let round = counts
+                .iter()
+                .map(|(id, count)| OptionSummary {
+                    option_id: *id,
+                    count: *count,
+                    percentage: percentage(*count, counted),
+                })
+                .collect();
+            let (leader, leader_count) = counts[0];
+            if leader_count * 2 > counted || counts.len() == 1 {
+                winner = Some(leader);
+                rounds.push(RunoffRound {
+                    options: round,
+                    eliminated: None,
+                });
+                break;
+            }
+            let (last, _) = counts[counts.len() - 1];
+            standing.retain(|id| *id != last);
+            rounds.push(RunoffRound {
+                options: round,
+                eliminated: Some(last),
+            });
+        }
+        RankingSummary {
+            borda,
+            rounds,
+            winner,
+        }
+    }
+}
+
+#[Object]
+impl RankingSummary {
+    async fn borda(&self) -> &[BordaScore] {
+        &self.borda
+    }
+    async fn runoff_rounds(&self) -> &[RunoffRound] {
+        &self.rounds
+    }
+    /// The instant-runoff winner
+    async fn winner_id(&self) -> Option<String> {
+        self.winner.map(|id| id.to_string())
+    }
+}
+
+#[Object]
+impl BordaScore {
+    async fn id(&self) -> String {
+        self.option_id.to_string()
+    }
+    async fn points(&self) -> i64 {
+        self.points
+    }
+}
+
+#[Object]
+impl RunoffRound {
+    async fn options(&self) -> &[OptionSummary] {
+        &self.options
+    }
+    async fn eliminated_id(&self) -> Option<String> {
+        self.eliminated.map(|id| id.to_string())
+    }
+}
+
+#[test]
+fn test_scale_summary() {
+    let scale = ScaleRange {
+        min: 0,
+        max: 10,
+        step: 5,
+    };
+    let s = ScaleSummary::from_counts(scale, &HashMap::from([(0, 1), (5, 2), (10, 1), (7, 3)]));
+    assert_eq!(s.total_count, 4);
+    assert_eq!(
+        s.buckets
+            .iter()
+            .map(|b| (b.value, b.count))
+            .collect::<Vec<_>>(),
+        vec![(0, 1), (5, 2), (10, 1)]
+    );
+    assert_eq!(s.buckets[1].percentage, 50);
+    assert_eq!(s.mean, Some(5.));
+    assert_eq!(s.median, Some(5.));
+    let s = ScaleSummary::from_counts(scale, &HashMap::from([(0, 1), (10, 1)]));
+    assert_eq!(s.median, Some(5.));
+    let s = ScaleSummary::from_counts(scale, &HashMap::new());
+    assert_eq!((s.mean, s.median, s.buckets.len()), (None, None, 3));
+}
+
+#[test]
+fn test_ranking_summary() {
+    // 1 leads on first choices without a majority, 3's voters prefer 2
+    let ballots = [
+        vec![1, 2, 3],
+        vec![1, 3, 2],
+        vec![2, 1, 3],
+        vec![2, 3, 1],
+        vec![3, 2, 1],
+    ];
+    let r = RankingSummary::from_ballots(&[1, 2, 3], &ballots);
+    assert_eq!(
+        r.borda
+            .iter()
+            .map(|b| (b.option_id, b.points))
+            .collect::<Vec<_>>(),
+        vec![(2, 6), (1, 5), (3, 4)]
+    );
+    assert_eq!(r.rounds.len(), 2);
+    assert_eq!(r.rounds[0].eliminated, Some(3));
+    assert_eq!(r.rounds[1].options[0].count, 3);
+    assert_eq!(r.winner, Some(2));
+    let r = RankingSummary::from_ballots(&[1, 2], &[]);
+    assert_eq!((r.rounds.len(), r.winner), (0, None));
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct OptionSummary {
     option_id: i64,
     count: i64,
@@ -835,6 +3457,7 @@ impl OptionSummary {
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct QuestionMultiOption {
     pub id: i64,
     pub question_id: i64,
@@ -861,42 +3484,205 @@ impl QuestionMultiOption {
     }
 }
 
-#[derive(Clone, sqlx::FromRow)]
-pub struct PinionWithFriendRelation {
-    pub id: i64,
-    pub user_id: i64,
-    pub requestor_id: i64,
-    pub acceptor_id: i64,
-    pub question_id: i64,
-    pub multi_selection: i64,
-    pub deleted: bool,
-    pub created: DateTime<Utc>,
-    pub modified: DateTime<Utc>,
+/// A response to a question, one variant per question kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Multi(i64),
+    Scale(i64),
+    Text(String),
+    /// Option ids, most preferred first
+    Ranking(Vec<i64>),
+}
+
+impl Answer {
+    pub fn question_kind(&self) -> &'static str {
+        match self {
+            Answer::Multi(_) => Question::MULTI,
+            Answer::Scale(_) => Question::SCALE,
+            Answer::Text(_) => Question::TEXT,
+            Answer::Ranking(_) => Question::RANKING,
+        }
+    }
+
+    pub fn multi_selection(&self) -> Option<i64> {
+        match self {
+            Answer::Multi(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+#[Object]
+impl Answer {
+    /// The kind of question answered: multi, scale, text or ranking
+    async fn kind(&self) -> &str {
+        self.question_kind()
+    }
+    async fn multi_selection_id(&self) -> Option<String> {
+        self.multi_selection().map(|id| id.to_string())
+    }
+    async fn scale_value(&self) -> Option<i64> {
+        match self {
+            Answer::Scale(v) => Some(*v),
+            _ => None,
+        }
+    }
+    async fn text(&self) -> Option<&str> {
+        match self {
+            Answer::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+    /// Option ids, most preferred first
+    async fn ranking_ids(&self) -> Option<Vec<String>> {
+        match self {
+            Answer::Ranking(ids) => Some(ids.iter().map(|id| id.to_string()).collect()),
+            _ => None,
+        }
+    }
 }
 
+/// Exactly one of the answer columns is set, matching the question's kind
 #[derive(Clone, sqlx::FromRow)]
 pub struct Pinion {
     pub id: i64,
     pub user_id: i64,
     pub question_id: i64,
-    pub multi_selection: i64,
+    pub multi_selection: Option<i64>,
+    pub scale_value: Option<i64>,
+    pub text_value: Option<String>,
+    pub ranking: Option<Vec<i64>>,
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
 }
 
-impl From<PinionWithFriendRelation> for Pinion {
-    fn from(p: PinionWithFriendRelation) -> Self {
-        Self {
-            id: p.id,
-            user_id: p.user_id,
-            question_id: p.question_id,
-            multi_selection: p.multi_selection,
-            deleted: p.deleted,
-            created: p.created,
-            modified: p.modified,
+impl Pinion {
+    /// The user's current answer to a question, locked for update, and when
+    /// they first answered it
+    pub async fn fetch_current(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: i64,
+        question_id: i64,
+    ) -> Result<Option<(Pinion, DateTime<Utc>)>> {
+        use sqlx::{FromRow, Row};
+        let row = sqlx::query(
+            r##"
+            select p.*, (
+                select min(h.created) from pin.pinions h
+                where h.user_id = p.user_id and h.question_id = p.question_id
+            ) as first_answered
+            from pin.pinions p
+            where p.user_id = $1 and p.question_id = $2 and p.deleted is false
+            for update
+            "##,
+        )
+        .bind(user_id)
+        .bind(question_id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        row.map(|row| {
+            Ok((
+                Pinion::from_row(&row).map_err(AppError::from)?,
+                row.try_get("first_answered").map_err(AppError::from)?,
+            ))
+        })
+        .transpose()
+    }
+
+    /// When an answer first given at `first_answered` becomes final,
+    /// `None` when `PINION_LOCK_SECONDS` isn't set
+    pub fn lock_time(first_answered: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        crate::CONFIG
+            .pinion_lock_seconds
+            .map(|secs| first_answered + chrono::Duration::seconds(secs))
+    }
+
+    pub fn to_answer(&self) -> Answer {
+        if let Some(id) = self.multi_selection {
+            Answer::Multi(id)
+        } else if let Some(value) = self.scale_value {
+            Answer::Scale(value)
+        } else if let Some(ranking) = &self.ranking {
+            Answer::Ranking(ranking.clone())
+        } else {
+            Answer::Text(self.text_value.clone().unwrap_or_default())
         }
     }
+
+    /// Save `user_id`'s answer, the answer must already be validated
+    pub async fn insert(
+        tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: i64,
+        question_id: i64,
+        answer: &Answer,
+    ) -> Result<Pinion> {
+        let (multi, scale, text, ranking) = match answer {
+            Answer::Multi(id) => (Some(*id), None, None, None),
+            Answer::Scale(v) => (None, Some(*v), None, None),
+            Answer::Text(t) => (None, None, Some(t.as_str()), None),
+            Answer::Ranking(ids) => (None, None, None, Some(ids.as_slice())),
+        };
+        let pinion = sqlx::query_as(
+            r##"
+            insert into pin.pinions
+                (user_id, question_id, multi_selection, scale_value, text_value, ranking)
+                values ($1, $2, $3, $4, $5, $6)
+                returning *
+            "##,
+        )
+        .bind(user_id)
+        .bind(question_id)
+        .bind(multi)
+        .bind(scale)
+        .bind(text)
+        .bind(ranking)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)?;
+        Ok(pinion)
+    }
+
+    /// Load a pinion if it belongs to `user_id` or one of their friends, and
+    /// neither has blocked the other. Pinions on group questions also need
+    /// `user_id` to be a member of the group
+    pub async fn fetch_visible(pool: &PgPool, id: i64, user_id: i64) -> Result<Option<Pinion>> {
+        let p = sqlx::query_as(
+            r##"
+            select p.* from pin.pinions p
+                where p.id = $1
+                    and (
+                        p.user_id = $2
+                        or exists(
+                            select 1 from pin.friends f
+                            where f.accepted is not null
+                                and f.deleted is false
+                                and (
+                                    (f.requestor_id = $2 and f.acceptor_id = p.user_id)
+                                    or (f.acceptor_id = $2 and f.requestor_id = p.user_id)
+                                )
+                        )
+                    )
+                    and not pin.is_blocked($2, p.user_id)
+                    and not exists(
+                        select 1 from pin.questions q
+                        where q.id = p.question_id
+                            and q.group_id is not null
+                            and q.group_id not in (
+                                select ga.group_id from pin.group_associations ga
+                                where ga.user_id = $2 and ga.deleted is false
+                            )
+                    )
+            "##,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::from)?;
+        Ok(p)
+    }
 }
 
 #[Object]
@@ -907,17 +3693,77 @@ impl Pinion {
     async fn question_id(&self) -> String {
         self.question_id.to_string()
     }
-    async fn multi_selection_id(&self) -> String {
-        self.multi_selection.to_string()
+    /// The selected option, for multiple choice questions
+    async fn multi_selection_id(&self) -> Option<String> {
+        self.multi_selection.map(|id| id.to_string())
+    }
+    async fn answer(&self) -> Answer {
+        self.to_answer()
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+    /// Answers this one replaced, earliest first
+    async fn history(&self, ctx: &Context<'_>) -> FieldResult<Vec<PreviousAnswer>> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let previous: Vec<Pinion> = sqlx::query_as(
+            r##"
+            select * from pin.pinions
+                where user_id = $1
+                    and question_id = $2
+                    and deleted is true
+                    and created < $3
+                order by created, id
+            "##,
+        )
+        .bind(self.user_id)
+        .bind(self.question_id)
+        .bind(self.created)
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error loading pinion history")
+        .extend()?;
+        Ok(previous
+            .into_iter()
+            .map(|p| PreviousAnswer {
+                answer: p.to_answer(),
+                answered: p.created,
+                replaced: p.modified,
+            })
+            .collect())
+    }
+    /// When this answer can no longer be changed, null if answers never lock
+    async fn locked_at(&self, ctx: &Context<'_>) -> FieldResult<Option<DateTime<Utc>>> {
+        if crate::CONFIG.pinion_lock_seconds.is_none() {
+            return Ok(None);
+        }
+        let pool = ctx.data_unchecked::<PgPool>();
+        let (first_answered,): (DateTime<Utc>,) = sqlx::query_as(
+            r##"
+            select min(created) from pin.pinions
+                where user_id = $1 and question_id = $2
+            "##,
+        )
+        .bind(self.user_id)
+        .bind(self.question_id)
+        .fetch_one(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error loading first answer time")
+        .extend()?;
+        Ok(Pinion::lock_time(first_answered))
     }
     /// The user who submitted this pinion
     async fn user(&self) -> FieldResult<User> {
         todo!()
     }
-    /// A list of all comments ordered sequentially from earliest to latest
+    /// A list of all comments ordered sequentially from earliest to latest.
+    /// Comments by users blocked by or blocking the current user are left out
     async fn comments(&self, ctx: &Context<'_>) -> FieldResult<Vec<Comment>> {
+        let u = ctx.data_opt::<User>().expect("no current user");
         ctx.data_unchecked::<AppLoader>()
-            .load_one(CommentsForPinion(self.id))
+            .load_one(CommentsForPinion(self.id, u.id))
             .await?
             .ok_or_else(|| {
                 AppError::from(format!("unable to load comments for pinion {}", self.id))
@@ -926,12 +3772,39 @@ impl Pinion {
     }
 }
 
-#[derive(Clone, sqlx::FromRow)]
+/// An answer that was later changed
+#[derive(Clone)]
+pub struct PreviousAnswer {
+    pub answer: Answer,
+    pub answered: DateTime<Utc>,
+    pub replaced: DateTime<Utc>,
+}
+
+#[Object]
+impl PreviousAnswer {
+    async fn answer(&self) -> &Answer {
+        &self.answer
+    }
+    /// The selected option, for multiple choice questions
+    async fn multi_selection_id(&self) -> Option<String> {
+        self.answer.multi_selection().map(|id| id.to_string())
+    }
+    async fn answered(&self) -> DateTime<Utc> {
+        self.answered
+    }
+    /// When the user changed their mind
+    async fn replaced(&self) -> DateTime<Utc> {
+        self.replaced
+    }
+}
+
+#[derive(Clone)]
+#[allow(dead_code)]
 pub struct FriendPinion {
     pub id: i64,
     pub user_id: i64,
     pub question_id: i64,
-    pub multi_selection: i64,
+    pub answer: Answer,
     pub deleted: bool,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
@@ -942,7 +3815,7 @@ impl From<Pinion> for FriendPinion {
             id: p.id,
             user_id: p.user_id,
             question_id: p.question_id,
-            multi_selection: p.multi_selection,
+            answer: p.to_answer(),
             deleted: p.deleted,
             created: p.created,
             modified: p.modified,
@@ -966,12 +3839,66 @@ impl FriendPinion {
     async fn question_id(&self) -> String {
         self.question_id.to_string()
     }
-    async fn multi_selection_id(&self) -> String {
-        self.multi_selection.to_string()
+    /// The selected option, for multiple choice questions
+    async fn multi_selection_id(&self) -> Option<String> {
+        self.answer.multi_selection().map(|id| id.to_string())
+    }
+    async fn answer(&self) -> &Answer {
+        &self.answer
+    }
+}
+
+/// A group member's pinion, members are only shown to each other by handle
+#[derive(Clone)]
+pub struct GroupPinion {
+    pub id: i64,
+    pub user_id: i64,
+    pub question_id: i64,
+    pub answer: Answer,
+    pub created: DateTime<Utc>,
+}
+impl From<Pinion> for GroupPinion {
+    fn from(p: Pinion) -> Self {
+        Self {
+            id: p.id,
+            user_id: p.user_id,
+            question_id: p.question_id,
+            answer: p.to_answer(),
+            created: p.created,
+        }
+    }
+}
+
+#[Object]
+impl GroupPinion {
+    async fn id(&self) -> String {
+        self.id.to_string()
+    }
+    async fn user(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        ctx.data_unchecked::<AppLoader>()
+            .load_one(UserId(self.user_id))
+            .await?
+            .map(SimpleUser::from)
+            .ok_or_else(|| AppError::from(format!("unable to load group member {}", self.user_id)))
+            .extend()
+    }
+    async fn question_id(&self) -> String {
+        self.question_id.to_string()
+    }
+    /// The selected option, for multiple choice questions
+    async fn multi_selection_id(&self) -> Option<String> {
+        self.answer.multi_selection().map(|id| id.to_string())
+    }
+    async fn answer(&self) -> &Answer {
+        &self.answer
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
     }
 }
 
 #[derive(Clone, sqlx::FromRow)]
+#[allow(dead_code)]
 pub struct Comment {
     pub id: i64,
     pub pinion_id: i64,
@@ -982,18 +3909,46 @@ pub struct Comment {
     pub modified: DateTime<Utc>,
 }
 
+impl Comment {
+    pub async fn fetch(pool: &PgPool, id: i64) -> Result<Option<Comment>> {
+        let c =
+            sqlx::query_as(r##"select * from pin.comments where id = $1 and deleted is false"##)
+                .bind(id)
+                .fetch_optional(pool)
+                .await
+                .map_err(AppError::from)?;
+        Ok(c)
+    }
+}
+
 #[Object]
 impl Comment {
     async fn id(&self) -> String {
         self.id.to_string()
     }
     /// The parent pinion this comment was made on
-    async fn pinion(&self) -> FieldResult<Pinion> {
-        todo!()
+    async fn pinion(&self, ctx: &Context<'_>) -> FieldResult<Pinion> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let p = sqlx::query_as(r##"select * from pin.pinions where id = $1"##)
+            .bind(self.pinion_id)
+            .fetch_one(pool)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| format!("error loading pinion of comment {}", self.id))
+            .extend()?;
+        Ok(p)
     }
-    /// The user that made this comment
-    async fn user(&self) -> FieldResult<User> {
-        todo!()
+    /// The user that made this comment, possibly not a friend of the viewer
+    async fn user(&self, ctx: &Context<'_>) -> FieldResult<SimpleUser> {
+        ctx.data_unchecked::<AppLoader>()
+            .load_one(UserId(self.user_id))
+            .await?
+            .map(SimpleUser::from)
+            .ok_or_else(|| AppError::from(format!("unable to load comment user {}", self.user_id)))
+            .extend()
+    }
+    async fn created(&self) -> DateTime<Utc> {
+        self.created
     }
     async fn content(&self) -> &str {
         &self.content
@@ -0,0 +1,416 @@
+/*!
+Phone number parsing and hashing
+
+Every phone number that enters the app is normalized to E.164 (`+<country code><number>`)
+so the same number always compares equal however it was typed.
+*/
+use crate::{AppError, Result, CONFIG};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+struct Region {
+    code: &'static str,
+    calling_code: &'static str,
+    // prefix dialed before national numbers that isn't part of the E.164 number,
+    // national significant numbers never start with it
+    trunk_prefix: Option<&'static str>,
+    // prefix dialed before international numbers (in addition to `+`)
+    international_prefix: &'static str,
+    national_lengths: &'static [usize],
+}
+
+static REGIONS: &[Region] = &[
+    Region {
+        code: "US",
+        calling_code: "1",
+        trunk_prefix: Some("1"),
+        international_prefix: "011",
+        national_lengths: &[10],
+    },
+    Region {
+        code: "CA",
+        calling_code: "1",
+        trunk_prefix: Some("1"),
+        international_prefix: "011",
+        national_lengths: &[10],
+    },
+    Region {
+        code: "GB",
+        calling_code: "44",
+        trunk_prefix: Some("0"),
+        international_prefix: "00",
+        national_lengths: &[9, 10],
+    },
+    Region {
+        code: "IE",
+        calling_code: "353",
+        trunk_prefix: Some("0"),
+        international_prefix: "00",
+        national_lengths: &[7, 8, 9],
+    },
+    Region {
+        code: "AU",
+        calling_code: "61",
+        trunk_prefix: Some("0"),
+        international_prefix: "0011",
+        national_lengths: &[9],
+    },
+    Region {
+        code: "NZ",
+        calling_code: "64",
+        trunk_prefix: Some("0"),
+        international_prefix: "00",
+        national_lengths: &[8, 9, 10],
+    },
+    Region {
+        code: "DE",
+        calling_code: "49",
+        trunk_prefix: Some("0"),
+        international_prefix: "00",
+        national_lengths: &[6, 7, 8, 9, 10, 11],
+    },
+    Region {
+        code: "FR",
+        calling_code: "33",
+        trunk_prefix: Some("0"),
+        international_prefix: "00",
+        national_lengths: &[9],
+    },
+    Region {
+        code: "IN",
+        calling_code: "91",
+        trunk_prefix: Some("0"),
+        international_prefix: "00",
+        national_lengths: &[10],
+    },
+    Region {
+        code: "MX",
+        calling_code: "52",
+        trunk_prefix: None,
+        international_prefix: "00",
+        national_lengths: &[10],
+    },
+];
+
+fn invalid(number: &str) -> AppError {
+    AppError::InvalidPhoneNumber(format!("invalid phone number: {number}"))
+}
+
+/// Normalize `number` to E.164, treating numbers without a country code
+/// as belonging to `DEFAULT_PHONE_REGION`
+pub fn normalize(number: &str) -> Result<String> {
+    normalize_with_region(number, &CONFIG.default_phone_region)
+}
+
+pub fn normalize_with_region(number: &str, region: &str) -> Result<String> {
+    let region = REGIONS
+        .iter()
+        .find(|r| r.code.eq_ignore_ascii_case(region))
+        .ok_or_else(|| AppError::from(format!("unsupported phone region: {region}")))?;
+
+    let trimmed = number.trim();
+    let mut digits = String::with_capacity(trimmed.len());
+    for c in trimmed.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            ' ' | '-' | '.' | '(' | ')' | '/' | '\u{a0}' => (),
+            '+' if digits.is_empty() => (),
+            _ => return Err(invalid(number)),
+        }
+    }
+    // guard against absurd inputs before doing anything else
+    if digits.len() > 20 {
+        return Err(invalid(number));
+    }
+
+    let international = if trimmed.starts_with('+') {
+        digits
+    } else if let Some(rest) = digits.strip_prefix(region.international_prefix) {
+        rest.to_string()
+    } else {
+        let national = match region.trunk_prefix {
+            Some(trunk) => digits.strip_prefix(trunk).unwrap_or(&digits),
+            None => digits.as_str(),
+        };
+        if !region.national_lengths.contains(&national.len()) {
+            return Err(invalid(number));
+        }
+        format!("{}{}", region.calling_code, national)
+    };
+
+    // E.164 numbers are at most 15 digits and country codes never start with 0
+    if international.len() < 8 || international.len() > 15 || international.starts_with('0') {
+        return Err(invalid(number));
+    }
+    Ok(format!("+{international}"))
+}
+
+/// HMAC of a normalized number with `CONTACT_HASH_KEY`. Clients send these
+/// to `checkPhoneHashes` instead of the numbers in their contacts
+pub fn contact_hash(number: &str) -> String {
+    crate::crypto::hmac_sign_with_key(number, &CONFIG.contact_hash_key)
+}
+
+/// Fill in `pin.phones.number_hmac` for numbers that don't have one yet, or for
+/// every number when `all` is set (after changing `CONTACT_HASH_KEY`)
+pub async fn backfill_hashes(pool: &PgPool, all: bool) -> Result<u64> {
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        r##"
+        select id, number from pin.phones
+            where $1 or number_hmac is null
+        "##,
+    )
+    .bind(all)
+    .fetch_all(pool)
+    .await?;
+    let (ids, hashes): (Vec<i64>, Vec<String>) = rows
+        .into_iter()
+        .map(|(id, number)| (id, contact_hash(&number)))
+        .unzip();
+    let res = sqlx::query(
+        r##"
+        update pin.phones p
+            set number_hmac = t.number_hmac
+            from unnest($1::bigint[], $2::text[]) as t(id, number_hmac)
+            where p.id = t.id
+        "##,
+    )
+    .bind(&ids)
+    .bind(&hashes)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// A phone that `normalize_existing` couldn't parse or deleted
+#[derive(Debug)]
+pub struct SkippedPhone {
+    pub id: i64,
+    pub user_id: i64,
+    pub number: String,
+}
+
+#[derive(Debug, Default)]
+pub struct NormalizeReport {
+    /// Numbers rewritten to E.164
+    pub normalized: u64,
+    /// Numbers that don't parse with `DEFAULT_PHONE_REGION`, left as they are.
+    /// Nothing typed in can match them so their users need fixing by hand
+    pub unparseable: Vec<SkippedPhone>,
+    /// Verified numbers that normalize to the same number as a more recently
+    /// verified phone, these are deleted
+    pub removed: Vec<SkippedPhone>,
+}
+
+impl NormalizeReport {
+    pub fn log(&self) {
+        for p in &self.unparseable {
+            tracing::warn!(
+                phone_id = p.id,
+                user_id = p.user_id,
+                "phones: can't normalize {}, left as is",
+                p.number
+            );
+        }
+        for p in &self.removed {
+            tracing::warn!(
+                phone_id = p.id,
+                user_id = p.user_id,
+                "phones: deleted {}, a more recently verified phone has the same number",
+                p.number
+            );
+        }
+        tracing::info!("phones: normalized {} numbers", self.normalized);
+    }
+}
+
+/// Of the verified phones that share a normalized number, the most recently
+/// verified keeps it. Returns the ids of the others
+fn collision_losers(phones: &[(i64, String, DateTime<Utc>)]) -> Vec<i64> {
+    let mut keepers: HashMap<&str, (i64, DateTime<Utc>)> = HashMap::new();
+    for (id, number, verified) in phones {
+        let keeper = keepers.entry(number).or_insert((*id, *verified));
+        if *verified > keeper.1 {
+            *keeper = (*id, *verified);
+        }
+    }
+    phones
+        .iter()
+        .filter(|(id, number, _)| keepers[number.as_str()].0 != *id)
+        .map(|(id, _, _)| *id)
+        .collect()
+}
+
+/// Rewrite live phone numbers that aren't E.164 yet (added before numbers were
+/// normalized) with `normalize`, so lookups by any formatting find them.
+/// Collisions and numbers that don't parse are resolved or reported, see `NormalizeReport`
+pub async fn normalize_existing(pool: &PgPool) -> Result<NormalizeReport> {
+    let mut tr = pool.begin().await?;
+    let rows: Vec<(i64, i64, String, Option<DateTime<Utc>>)> = sqlx::query_as(
+        r##"
+        select id, user_id, number, verified from pin.phones
+            where deleted is false and number !~ '^\+[1-9][0-9]{7,14}$'
+        "##,
+    )
+    .fetch_all(&mut *tr)
+    .await?;
+
+    let mut report = NormalizeReport::default();
+    let mut changes = vec![];
+    for (id, user_id, number, verified) in rows {
+        match normalize(&number) {
+            Ok(n) => changes.push((id, user_id, number, n, verified)),
+            Err(_) => report.unparseable.push(SkippedPhone {
+                id,
+                user_id,
+                number,
+            }),
+        }
+    }
+
+    // verified numbers are unique, check the changed ones against each other
+    // and against verified numbers that were already normalized
+    let targets = changes
+        .iter()
+        .map(|(_, _, _, n, _)| n.clone())
+        .collect::<Vec<_>>();
+    let existing: Vec<(i64, i64, String, DateTime<Utc>)> = sqlx::query_as(
+        r##"
+        select id, user_id, number, verified from pin.phones
+            where deleted is false and verified is not null and number = any($1)
+        "##,
+    )
+    .bind(&targets)
+    .fetch_all(&mut *tr)
+    .await?;
+    let verified = changes
+        .iter()
+        .filter_map(|(id, _, _, n, v)| Some((*id, n.clone(), (*v)?)))
+        .chain(existing.iter().map(|(id, _, n, v)| (*id, n.clone(), *v)))
+        .collect::<Vec<_>>();
+    let losers = collision_losers(&verified);
+
+    report.removed = changes
+        .iter()
+        .map(|(id, user_id, number, _, _)| (*id, *user_id, number))
+        .chain(
+            existing
+                .iter()
+                .map(|(id, user_id, n, _)| (*id, *user_id, n)),
+        )
+        .filter(|(id, _, _)| losers.contains(id))
+        .map(|(id, user_id, number)| SkippedPhone {
+            id,
+            user_id,
+            number: number.clone(),
+        })
+        .collect();
+    sqlx::query(
+        r##"
+        update pin.phones set deleted = true, modified = now()
+            where id = any($1)
+        "##,
+    )
+    .bind(&losers)
+    .execute(&mut *tr)
+    .await?;
+
+    let (ids, numbers): (Vec<i64>, Vec<String>) = changes
+        .into_iter()
+        .filter(|(id, _, _, _, _)| !losers.contains(id))
+        .map(|(id, _, _, n, _)| (id, n))
+        .unzip();
+    let hashes = numbers.iter().map(|n| contact_hash(n)).collect::<Vec<_>>();
+    let res = sqlx::query(
+        r##"
+        update pin.phones p
+            set number = t.number, number_hmac = t.number_hmac, modified = now()
+            from unnest($1::bigint[], $2::text[], $3::text[]) as t(id, number, number_hmac)
+            where p.id = t.id
+        "##,
+    )
+    .bind(&ids)
+    .bind(&numbers)
+    .bind(&hashes)
+    .execute(&mut *tr)
+    .await?;
+    report.normalized = res.rows_affected();
+    tr.commit().await?;
+    Ok(report)
+}
+
+#[test]
+fn test_normalize_formats() {
+    for n in [
+        "+1 (555) 123-4567",
+        "5551234567",
+        "555.123.4567",
+        "1-555-123-4567",
+        "011 1 555 123 4567",
+        " +15551234567 ",
+    ] {
+        assert_eq!(
+            normalize_with_region(n, "US").unwrap(),
+            "+15551234567",
+            "{n}"
+        );
+    }
+    assert_eq!(
+        normalize_with_region("07911 123456", "GB").unwrap(),
+        "+447911123456"
+    );
+    assert_eq!(
+        normalize_with_region("+44 7911 123456", "US").unwrap(),
+        "+447911123456"
+    );
+}
+
+#[test]
+fn test_normalize_rejects_invalid() {
+    for n in [
+        "",
+        "555-1234",
+        "call me",
+        "+0123456789",
+        "+1234567890123456",
+        "12+34",
+    ] {
+        assert!(normalize_with_region(n, "US").is_err(), "{n}");
+    }
+    assert!(normalize_with_region("5551234567", "XX").is_err());
+}
+
+#[test]
+fn test_contact_hash_matches_hmac() {
+    let number = normalize_with_region("555-123-4567", "US").unwrap();
+    let hash = contact_hash(&number);
+    assert_eq!(hash.len(), 64);
+    assert!(crate::crypto::hmac_verify_with_key(
+        "+15551234567",
+        &hash,
+        &CONFIG.contact_hash_key
+    ));
+}
+
+#[test]
+fn test_collision_losers_keep_latest_verified() {
+    let at = |h| {
+        DateTime::<Utc>::from_utc(
+            chrono::NaiveDate::from_ymd_opt(2026, 10, 1)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap(),
+            Utc,
+        )
+    };
+    let phones = [
+        (1, "+15551234567".to_string(), at(1)),
+        (2, "+15551234567".to_string(), at(3)),
+        (3, "+15551234567".to_string(), at(2)),
+        (4, "+15557654321".to_string(), at(1)),
+    ];
+    let mut losers = collision_losers(&phones);
+    losers.sort();
+    assert_eq!(losers, vec![1, 3]);
+}
@@ -0,0 +1,255 @@
+/*!
+Question of the day calendar
+
+`pin.question_schedule` maps days to questions. The question of the day for a
+date is the one scheduled on the latest day on or before it, so days that aren't
+question days (e.g. weekends with `QOD_SCHEDULE=weekdays`) keep the previous
+question. Admins schedule questions on specific days, every other question day
+is filled in by priority.
+
+Each group with its own questions gets a separate calendar (rows with the
+group's `group_id`), filled the same way from the group's queue.
+*/
+use crate::error::LogError;
+use crate::models::{Question, ScheduledQuestion};
+use crate::{AppError, Result, CONFIG};
+use bdays::HolidayCalendar;
+use chrono::{Duration, NaiveDate};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Which days get a new question of the day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleMode {
+    Daily,
+    Weekdays,
+    // weekdays that aren't US settlement holidays
+    BusinessDays,
+}
+
+impl FromStr for ScheduleMode {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(ScheduleMode::Daily),
+            "weekdays" => Ok(ScheduleMode::Weekdays),
+            "business_days" => Ok(ScheduleMode::BusinessDays),
+            _ => Err(format!("unknown schedule mode: {s}")),
+        }
+    }
+}
+
+impl ScheduleMode {
+    pub fn is_question_day(&self, day: NaiveDate) -> bool {
+        match self {
+            ScheduleMode::Daily => true,
+            ScheduleMode::Weekdays => bdays::is_weekday(day),
+            ScheduleMode::BusinessDays => bdays::calendars::us::USSettlement.is_bday(day),
+        }
+    }
+}
+
+/// The current day in the zone questions roll over in
+pub async fn today(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<NaiveDate> {
+    let (today,): (NaiveDate,) =
+        sqlx::query_as(r##"select timezone('America/New_York', now())::date"##)
+            .fetch_one(&mut *tr)
+            .await
+            .map_err(AppError::from)?;
+    Ok(today)
+}
+
+/// The latest day that may already be someone's question of the day. Users in
+/// zones ahead of the server's (up to UTC+14) are on tomorrow's question while
+/// it's still today here, so neither day can change
+pub fn last_live_day(today: NaiveDate) -> NaiveDate {
+    today + Duration::days(1)
+}
+
+/// Fill in question days from today through the lookahead window by priority
+/// and mark today's question as used, for the global calendar and every group
+/// calendar. Automatically scheduled days after `last_live_day` are reassigned
+/// when priorities change, live questions never change once set.
+pub async fn fill(pool: &PgPool) -> Result<()> {
+    let mut tr = pool
+        .begin()
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "schedule: error starting transaction")?;
+    // only one server fills the schedule at a time
+    sqlx::query(r##"select pg_advisory_xact_lock(hashtext('pin.question_schedule'))"##)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "schedule: error locking schedule")?;
+
+    let today = today(&mut tr).await?;
+    let mut newly_used = vec![];
+    newly_used.extend(fill_calendar(&mut tr, None, today).await?);
+    // groups with questions waiting in their queue
+    let groups: Vec<(i64,)> = sqlx::query_as(
+        r##"
+        select distinct q.group_id from pin.questions q
+            inner join pin.groups g on g.id = q.group_id
+            where q.deleted is false
+                and q.used is null
+                and g.deleted is false
+        "##,
+    )
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "schedule: error loading group queues")?;
+    for (group_id,) in groups {
+        newly_used.extend(fill_calendar(&mut tr, Some(group_id), today).await?);
+    }
+
+    tr.commit()
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "schedule: error committing schedule")?;
+
+    // start the new questions' tallies at zero for every option
+    for q in newly_used {
+        crate::tally::recount_question(pool, q.id)
+            .await
+            .log_error_msg(|| "schedule: error initializing tallies")?;
+    }
+    Ok(())
+}
+
+/// Fill one calendar, returning the current question if it was just marked used
+async fn fill_calendar(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    group_id: Option<i64>,
+    today: NaiveDate,
+) -> Result<Option<Question>> {
+    let last = today + Duration::days(CONFIG.question_schedule_lookahead_days.max(1));
+    let live = last_live_day(today);
+    let scheduled = ScheduledQuestion::fetch_range(tr, group_id, today, last)
+        .await
+        .log_error_msg(|| "schedule: error loading schedule")?
+        .into_iter()
+        .map(|s| (s.day, s))
+        .collect::<HashMap<_, _>>();
+    let (has_past,): (bool,) = sqlx::query_as(
+        r##"
+        select exists(
+            select 1 from pin.question_schedule
+            where day < $1 and group_id is not distinct from $2 and deleted is false
+        )
+        "##,
+    )
+    .bind(today)
+    .bind(group_id)
+    .fetch_one(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "schedule: error checking past schedule")?;
+
+    // questions that can still be placed, automatic days after the live ones
+    // are recomputed so they're included
+    let candidates: Vec<Question> = sqlx::query_as(
+        r##"
+        select * from pin.questions
+            where deleted is false
+                and used is null
+                and group_id is not distinct from $3
+                and id not in (
+                    select question_id from pin.question_schedule
+                    where deleted is false and (auto is false or day <= $1)
+                )
+            order by priority asc, created asc
+            limit $2
+        "##,
+    )
+    .bind(live)
+    .bind((last - today).num_days() + 1)
+    .bind(group_id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "schedule: error loading question queue")?;
+    let mut candidates = candidates.into_iter();
+
+    let mut day = today;
+    while day <= last {
+        let existing = scheduled.get(&day);
+        let fixed = existing.map(|s| !s.auto || s.day <= live).unwrap_or(false);
+        // there always has to be something to show today
+        let question_day =
+            CONFIG.question_schedule.is_question_day(day) || (day == today && !has_past);
+        if fixed {
+            // admin scheduled, or possibly live
+        } else if question_day {
+            match (candidates.next(), existing) {
+                (None, _) => (),
+                (Some(q), Some(s)) if s.question_id == q.id => (),
+                (Some(q), Some(s)) => {
+                    tracing::info!(day = %day, question_id = q.id, group_id = ?group_id, "rescheduling question");
+                    ScheduledQuestion::set_question(tr, s.id, q.id)
+                        .await
+                        .log_error_msg(|| "schedule: error updating day")?;
+                }
+                (Some(q), None) => {
+                    tracing::info!(day = %day, question_id = q.id, group_id = ?group_id, "scheduling question");
+                    ScheduledQuestion::create(tr, group_id, day, q.id, true)
+                        .await
+                        .log_error_msg(|| "schedule: error scheduling day")?;
+                }
+            }
+        } else if let Some(s) = existing {
+            ScheduledQuestion::delete(tr, s.id)
+                .await
+                .log_error_msg(|| "schedule: error removing day")?;
+        }
+        day += Duration::days(1);
+    }
+
+    let current: Option<Question> = sqlx::query_as(
+        r##"
+        select q.* from pin.question_schedule s
+            inner join pin.questions q on q.id = s.question_id
+            where s.deleted is false
+                and s.day <= $1
+                and s.group_id is not distinct from $2
+            order by s.day desc
+            limit 1
+        "##,
+    )
+    .bind(today)
+    .bind(group_id)
+    .fetch_optional(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "schedule: error loading current question")?;
+    let newly_used = current.filter(|q| q.used.is_none());
+    if let Some(q) = &newly_used {
+        tracing::info!(question_id = q.id, group_id = ?group_id, "marking question of the day used");
+        Question::mark_used(q.id, tr)
+            .await
+            .log_error_msg(|| "schedule: error marking question used")?;
+    }
+    Ok(newly_used)
+}
+
+#[test]
+fn test_question_days() {
+    // fri, sat, mon, and thanksgiving
+    let fri = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+    let sat = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap();
+    let mon = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+    let thanksgiving = NaiveDate::from_ymd_opt(2026, 11, 26).unwrap();
+    for d in [fri, sat, mon, thanksgiving] {
+        assert!(ScheduleMode::Daily.is_question_day(d));
+    }
+    assert!(ScheduleMode::Weekdays.is_question_day(fri));
+    assert!(!ScheduleMode::Weekdays.is_question_day(sat));
+    assert!(ScheduleMode::Weekdays.is_question_day(thanksgiving));
+    assert!(ScheduleMode::BusinessDays.is_question_day(mon));
+    assert!(!ScheduleMode::BusinessDays.is_question_day(sat));
+    assert!(!ScheduleMode::BusinessDays.is_question_day(thanksgiving));
+    assert!("weekdays".parse::<ScheduleMode>().is_ok());
+    assert!("hourly".parse::<ScheduleMode>().is_err());
+}
@@ -1,16 +1,28 @@
 use crate::crypto::{b64_encode, encrypt};
-use crate::loaders::{AppLoader, QuestionOfDay};
+use crate::events::Events;
+use crate::loaders::{AppLoader, ProfileForUserId, QuestionId};
 use crate::models::{
-    BaseUser, ChallengePhone, Friend, LoginSuccess, Phone, PhoneCheck, Pinion, PotentialFriendUser,
-    Question, User, VerificationCode,
+    question_summary, Answer, BaseUser, Block, ChallengePhone, Comment, CurrentSession, Friend,
+    FriendSuggestion, Group, GroupAssociation, GroupInvite, GroupRole, Invite, LoginSuccess,
+    NewQuestion, Password, Phone, PhoneCheck, PhoneHashMatch, Pinion, PotentialFriendUser,
+    Question, QuestionSummary, ScaleRange, ScheduledQuestion, Session, User, UserAgent,
+    VerificationCode,
 };
+use crate::sms::Sms;
 use crate::{error::LogError, AppError, Result, CONFIG};
 use async_graphql::{
-    Context, EmptySubscription, ErrorExtensions, FieldResult, Guard, Object, ResultExt,
+    Context, ErrorExtensions, FieldResult, Guard, Object, ResultExt, Subscription,
 };
-use chrono::Utc;
-use serde::Serialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{Stream, StreamExt};
 use sqlx::PgPool;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+/// Most hashes accepted by one `checkPhoneHashes` call, a large address book
+const MAX_CONTACT_HASHES: usize = 5000;
+
+/// Longest accepted password, hashing isn't free
+const PASSWORD_MAX_LENGTH: usize = 1024;
 
 struct LoginGuard;
 
@@ -58,6 +70,28 @@ impl Guard for LoginNeedsVerificationGuard {
     }
 }
 
+struct AdminGuard;
+
+impl AdminGuard {
+    fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Used to wrap entrypoints that manage app content, requires
+/// a logged in and verified admin user
+#[async_trait::async_trait]
+impl Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> FieldResult<()> {
+        LoginGuard::new().check(ctx).await?;
+        let u = ctx.data_unchecked::<User>();
+        if !u.admin {
+            return Err(AppError::Forbidden("Forbidden".into()).extend());
+        }
+        Ok(())
+    }
+}
+
 fn generate_clear_token() -> String {
     let clear_token = hex::encode(crate::crypto::rand_bytes(31).unwrap_or_else(|_| vec![0; 31]));
     format!("xxxx{clear_token}")
@@ -103,15 +137,17 @@ async fn login_ctx(ctx: &Context<'_>, user: &User) -> Result<String> {
             CONFIG.auth_expiration_seconds as i64,
         ))
         .ok_or_else(|| AppError::from("error calculating auth expiration"))?;
+    let user_agent = ctx.data_opt::<UserAgent>().map(|ua| ua.0.clone());
     sqlx::query(
         r##"
         insert into pin.auth_tokens
-            (user_id, hash, expires) values ($1, $2, $3)
+            (user_id, hash, expires, user_agent) values ($1, $2, $3, $4)
     "##,
     )
     .bind(user.id)
     .bind(token_hash)
     .bind(expires)
+    .bind(user_agent)
     .execute(pool)
     .await
     .map_err(|e| {
@@ -127,6 +163,26 @@ async fn login_ctx(ctx: &Context<'_>, user: &User) -> Result<String> {
 }
 
 async fn send_verification_code(ctx: &Context<'_>, user: &User) -> Result<String> {
+    send_code_to_phone(
+        ctx,
+        user.id,
+        &user.phone_number,
+        user.phone_verification_sent,
+        None,
+    )
+    .await
+}
+
+/// Generate, save, and text a verification code to one of the user's phones.
+/// `pending_phone_id` is set when the code is for confirming a phone number change,
+/// in which case it can't be used to verify the user's current phone.
+async fn send_code_to_phone(
+    ctx: &Context<'_>,
+    user_id: i64,
+    phone_number: &str,
+    verification_sent: Option<DateTime<Utc>>,
+    pending_phone_id: Option<i64>,
+) -> Result<String> {
     let pool = ctx.data_unchecked::<PgPool>();
 
     #[derive(Clone, sqlx::FromRow)]
@@ -141,14 +197,14 @@ async fn send_verification_code(ctx: &Context<'_>, user: &User) -> Result<String
             and created > now() - interval '60 seconds'
         "##,
     )
-    .bind(user.id)
+    .bind(user_id)
     .fetch_one(pool)
     .await
     .map_err(|e| {
         tracing::error!("error {:?}", e);
         AppError::from(e)
     })?;
-    if let Some(sent) = user.phone_verification_sent {
+    if let Some(sent) = verification_sent {
         if sent
             > Utc::now()
                 .checked_sub_signed(chrono::Duration::seconds(5))
@@ -169,17 +225,17 @@ async fn send_verification_code(ctx: &Context<'_>, user: &User) -> Result<String
     let hash = crate::crypto::derive_password_hash(code.as_bytes(), salt.as_ref());
     let salt = hex::encode(salt);
     let hash = hex::encode(hash);
-    let pool = ctx.data_unchecked::<PgPool>();
 
     sqlx::query(
         r##"
-            insert into pin.verification_codes (user_id, salt, hash)
-                values ($1, $2, $3)
+            insert into pin.verification_codes (user_id, salt, hash, phone_id)
+                values ($1, $2, $3, $4)
         "##,
     )
-    .bind(user.id)
+    .bind(user_id)
     .bind(salt)
     .bind(hash)
+    .bind(pending_phone_id)
     .execute(pool)
     .await
     .map_err(|e| {
@@ -194,9 +250,12 @@ async fn send_verification_code(ctx: &Context<'_>, user: &User) -> Result<String
                 verification_sent = now(),
                 verification_attempts = verification_attempts + 1
             where user_id = $1
+                and deleted is false
+                and pending = $2
         "##,
     )
-    .bind(user.id)
+    .bind(user_id)
+    .bind(pending_phone_id.is_some())
     .execute(pool)
     .await
     .map_err(|e| {
@@ -204,68 +263,153 @@ async fn send_verification_code(ctx: &Context<'_>, user: &User) -> Result<String
         AppError::from(e)
     })?;
 
-    #[derive(Serialize)]
-    struct Msg {
-        #[serde(rename = "To")]
-        to: String,
-        #[serde(rename = "MessagingServiceSid")]
-        msg_sid: String,
-        #[serde(rename = "Body")]
-        body: String,
-    }
-    let msg = Msg {
-        to: user.phone_number.clone(),
-        msg_sid: CONFIG.twilio_messaging_service_sid.clone(),
-        body: format!("Your Pinion code is {}", code),
-    };
-    let url = format!(
-        "https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json",
-        CONFIG.twilio_account
-    );
-    if CONFIG.allowed_phone_numbers.is_none()
-        || (CONFIG.allowed_phone_numbers.is_some()
-            && CONFIG
-                .allowed_phone_numbers
-                .as_ref()
-                .unwrap()
-                .contains(&user.phone_number))
-    {
-        tracing::info!("sending code to {}", &user.phone_number);
-        let _resp: serde_json::Value = reqwest::Client::new()
-            .post(&url)
-            .basic_auth(&CONFIG.twilio_sid, Some(&CONFIG.twilio_secret))
-            .form(&msg)
-            .send()
-            .await
-            .map_err(|e| {
-                tracing::error!("{:?}", e);
-                e
-            })?
-            .json()
-            .await
-            .map_err(|e| {
-                tracing::error!("{:?}", e);
-                e
-            })?;
-    }
+    send_sms(ctx, phone_number, &format!("Your Pinion code is {}", code)).await?;
     tracing::debug!("verification code: {}", code);
     Ok(code)
 }
 
-async fn _verify_code_for_user(
+/// Send a text through the configured sms backend. When `ALLOWED_PHONE_NUMBERS`
+/// is set, messages to any other number are skipped.
+async fn send_sms(ctx: &Context<'_>, to: &str, body: &str) -> Result<()> {
+    if let Some(allowed) = CONFIG.allowed_phone_numbers.as_ref() {
+        let is_allowed = allowed
+            .iter()
+            .any(|n| crate::phone::normalize(n).map(|n| n == to).unwrap_or(false));
+        if !is_allowed {
+            tracing::info!("skipping sms to {}, not an allowed number", to);
+            return Ok(());
+        }
+    }
+    tracing::info!("sending sms to {}", to);
+    ctx.data_unchecked::<Sms>().send(to, body).await
+}
+
+fn hash_password(password: &str) -> Result<(String, String)> {
+    let salt = crate::crypto::new_pw_salt()?;
+    let hash = crate::crypto::derive_password_hash(password.as_bytes(), &salt);
+    Ok((hex::encode(salt), hex::encode(hash)))
+}
+
+fn validate_new_password(password: &str) -> FieldResult<()> {
+    let len = password.chars().count();
+    if len < CONFIG.password_min_length {
+        return Err(AppError::BadRequest(format!(
+            "password must be at least {} characters",
+            CONFIG.password_min_length
+        ))
+        .extend()
+        .extend_with(|_e, ex| ex.set("key", "WEAK_PASSWORD")));
+    }
+    if len > PASSWORD_MAX_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "password must be at most {} characters",
+            PASSWORD_MAX_LENGTH
+        ))
+        .extend()
+        .extend_with(|_e, ex| ex.set("key", "PASSWORD_TOO_LONG")));
+    }
+    Ok(())
+}
+
+fn invalid_credentials() -> async_graphql::Error {
+    AppError::Unauthorized("invalid credentials".into())
+        .extend()
+        .extend_with(|_e, ex| ex.set("key", "INVALID_CREDENTIALS"))
+}
+
+/// Run the (deliberately slow) password hash on the blocking pool so it
+/// doesn't stall other requests on the same worker
+async fn derive_password_hash_blocking(
+    password: &str,
+    salt: Vec<u8>,
+) -> Result<[u8; ring::digest::SHA512_OUTPUT_LEN]> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || {
+        crate::crypto::derive_password_hash(password.as_bytes(), &salt)
+    })
+    .await
+    .map_err(|e| AppError::from(format!("password hash task failed: {e}")))
+}
+
+/// Check a password for the given user (or a dummy hash when there is no user
+/// so the response time doesn't reveal which accounts exist). Failures are
+/// counted and lock the password after `PASSWORD_MAX_FAILED_ATTEMPTS`.
+async fn verify_user_password(
+    pool: &PgPool,
+    user_id: Option<i64>,
+    password: &str,
+) -> FieldResult<()> {
+    let mut tr = pool
+        .begin()
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error starting transaction")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+    let saved = match user_id {
+        Some(user_id) => Password::fetch_for_user(&mut tr, user_id)
+            .await
+            .log_error_msg(|| format!("error loading password for user {user_id}"))
+            .extend()?,
+        None => None,
+    };
+    let saved = match saved {
+        None => {
+            derive_password_hash_blocking(password, vec![0; 128]).await?;
+            return Err(invalid_credentials());
+        }
+        Some(saved) => saved,
+    };
+    // hash before checking the lock so a locked account takes as long to answer
+    let saved_hash = hex::decode(&saved.hash)?;
+    let this_hash = derive_password_hash_blocking(password, hex::decode(&saved.salt)?).await?;
+    if saved.is_locked() {
+        tracing::info!("password for user {} is locked", saved.user_id);
+        return Err(AppError::Forbidden("too many failed attempts".into())
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "ACCOUNT_LOCKED")));
+    }
+
+    let matches = ring::constant_time::verify_slices_are_equal(&saved_hash, &this_hash).is_ok();
+    if matches {
+        saved.clear_failures(&mut tr).await.extend()?;
+    } else {
+        saved
+            .record_failure(
+                &mut tr,
+                CONFIG.password_max_failed_attempts,
+                CONFIG.password_lockout_seconds as i64,
+            )
+            .await
+            .log_error_msg(|| "error recording failed password attempt")
+            .extend()?;
+    }
+    tr.commit().await.map_err(AppError::from).extend()?;
+    if !matches {
+        return Err(invalid_credentials());
+    }
+    Ok(())
+}
+
+/// Check `code` against the latest code sent to the user (or to their pending
+/// phone when `pending_phone_id` is set) and consume it
+async fn _check_latest_code(
     tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    user: &User,
+    user_id: i64,
+    pending_phone_id: Option<i64>,
     code: &str,
-) -> Result<User> {
+) -> Result<()> {
     let latest_code: Option<VerificationCode> = sqlx::query_as(
         r##"
         select * from pin.verification_codes
-        where user_id = $1 and deleted is false
+        where user_id = $1
+            and phone_id is not distinct from $2
+            and deleted is false
         order by created desc
         limit 1
         "##,
     )
-    .bind(user.id)
+    .bind(user_id)
+    .bind(pending_phone_id)
     .fetch_optional(&mut *tr)
     .await
     .map_err(AppError::from)?;
@@ -298,23 +442,282 @@ async fn _verify_code_for_user(
     .execute(&mut *tr)
     .await
     .map_err(AppError::from)?;
+    Ok(())
+}
+
+async fn _verify_code_for_user(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user: &User,
+    code: &str,
+) -> Result<User> {
+    _check_latest_code(tr, user.id, None, code).await?;
 
     // Note: This will fail if someone has already verified this number. This is because we
     //       only enforce unique _verified_ numbers so that someone can't squat your number
     //       without being able to verify it. The potential downside is that if you legitimately
     //       enter the wrong (or someone elses) number at signup, then you won't realize until now.
-    //       Need to add another mutation to let you change your phone number (delete and recreate)
-    sqlx::query(r##"update pin.phones set verified = now(), modified = now() where user_id = $1"##)
-        .bind(latest_code.user_id)
-        .execute(&mut *tr)
-        .await
-        .map_err(AppError::from)?;
+    //       Use request_phone_change/confirm_phone_change to switch to the right number.
+    sqlx::query(
+        r##"
+        update pin.phones
+            set verified = now(), modified = now()
+            where user_id = $1 and deleted is false and pending is false
+        "##,
+    )
+    .bind(user.id)
+    .execute(&mut *tr)
+    .await
+    .map_err(AppError::from)?;
+
+    // the number is theirs now, so anyone who invited it becomes a friend
+    Invite::accept_for_user(tr, user.id, &user.phone_number).await?;
 
     let user = User::fetch_user(tr, user.id).await?;
 
     Ok(user)
 }
 
+/// Link a signing up user to the invite they followed. Stale or mangled codes
+/// are ignored rather than failing the signup
+async fn claim_invite(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    code: &str,
+    user_id: i64,
+) -> FieldResult<()> {
+    let claimed = Invite::claim(tr, code, user_id)
+        .await
+        .log_error_msg(|| "error claiming invite")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+    if !claimed {
+        tracing::warn!(user_id = %user_id, "ignoring invalid or used invite code");
+    }
+    Ok(())
+}
+
+/// Collect the flat question arguments of the question mutations
+fn new_question(
+    prompt: String,
+    options: Vec<String>,
+    kind: Option<String>,
+    scale_min: Option<i64>,
+    scale_max: Option<i64>,
+    scale_step: Option<i64>,
+) -> Result<NewQuestion> {
+    let scale = match (scale_min, scale_max) {
+        (Some(min), Some(max)) => Some(ScaleRange {
+            min,
+            max,
+            step: scale_step.unwrap_or(1),
+        }),
+        (None, None) if scale_step.is_none() => None,
+        _ => {
+            return Err(AppError::BadRequest(
+                "scales need both scaleMin and scaleMax".into(),
+            ))
+        }
+    };
+    Ok(NewQuestion {
+        kind: kind.unwrap_or_else(|| Question::MULTI.to_string()),
+        scale,
+        ..NewQuestion::multi(prompt, options)
+    })
+}
+
+async fn fetch_question_for_admin(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id: i64,
+) -> Result<Question> {
+    Question::fetch(tr, id)
+        .await
+        .log_error_msg(|| "error fetching question")?
+        // group questions are managed by the group's admins
+        .filter(|q| q.group_id.is_none())
+        .ok_or_else(|| AppError::BadRequest(format!("unknown question {id}")))
+}
+
+/// Questions scheduled on a day that may already be live somewhere can't be
+/// rescheduled or deleted, see `schedule::last_live_day`
+async fn check_question_not_live(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    question_id: i64,
+    today: NaiveDate,
+) -> FieldResult<()> {
+    let (live,): (bool,) = sqlx::query_as(
+        r##"
+        select exists(
+            select 1 from pin.question_schedule
+            where deleted is false and question_id = $1 and day <= $2
+        )
+        "##,
+    )
+    .bind(question_id)
+    .bind(crate::schedule::last_live_day(today))
+    .fetch_one(&mut *tr)
+    .await
+    .map_err(AppError::from)
+    .log_error_msg(|| "error checking live schedule")
+    .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+    if live {
+        return Err(AppError::BadRequest("question is already live".into())
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "QUESTION_ALREADY_USED")));
+    }
+    Ok(())
+}
+
+/// Bring the schedule up to date after the question queue changes, failures are
+/// only logged since the background job fills the schedule again shortly
+async fn refill_schedule(pool: &PgPool) {
+    crate::schedule::fill(pool)
+        .await
+        .log_error_msg(|| "error filling question schedule")
+        .ok();
+}
+
+/// Friend requests can't be sent between users when either has blocked the other,
+/// reported the same as an unknown user so the block isn't revealed
+async fn check_not_blocked(pool: &PgPool, user_id: i64, other_id: i64) -> FieldResult<()> {
+    if Block::exists_between(pool, user_id, other_id)
+        .await
+        .log_error_msg(|| "error checking blocks")
+        .extend()?
+    {
+        return Err(unknown_user());
+    }
+    Ok(())
+}
+
+/// Friend requests to users that don't exist, and to blocked users, see `check_not_blocked`
+fn unknown_user() -> async_graphql::Error {
+    AppError::BadRequest("unable to find other user".into())
+        .extend()
+        .extend_with(|_e, ex| ex.set("key", "UNKNOWN_USER"))
+}
+
+/// Lock a pending friend request that the user is the acceptor of, or the
+/// requestor of when `as_acceptor` is false
+async fn fetch_pending_friend_request(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id: i64,
+    user_id: i64,
+    as_acceptor: bool,
+) -> FieldResult<Friend> {
+    let f = Friend::fetch_for_update(tr, id)
+        .await
+        .log_error_msg(|| "error fetching friend request")
+        .extend()?
+        // requests between other users are reported as unknown
+        .filter(|f| f.requestor_id == user_id || f.acceptor_id == user_id)
+        .ok_or_else(|| AppError::BadRequest(format!("unknown friend request {id}")))
+        .extend_err(|_e, ex| ex.set("key", "UNKNOWN_FRIEND_REQUEST"))?;
+    if f.accepted.is_some() {
+        return Err(
+            AppError::BadRequest("friend request already accepted".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "ALREADY_FRIENDS")),
+        );
+    }
+    if as_acceptor && f.acceptor_id != user_id {
+        return Err(AppError::Forbidden("only the recipient can respond".into())
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "NOT_REQUEST_RECIPIENT")));
+    }
+    if !as_acceptor && f.requestor_id != user_id {
+        return Err(AppError::Forbidden("only the sender can cancel".into())
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "NOT_REQUEST_SENDER")));
+    }
+    Ok(f)
+}
+
+/// Lock a group and load the user's membership and role in it. Groups the user
+/// isn't a member of are reported the same as groups that don't exist
+async fn fetch_group_membership(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    group_id: i64,
+    user_id: i64,
+) -> Result<(Group, GroupAssociation, GroupRole)> {
+    let unknown = || AppError::BadRequest(format!("unknown group {group_id}"));
+    let group = Group::fetch_for_update(tr, group_id)
+        .await
+        .log_error_msg(|| "error fetching group")?
+        .ok_or_else(unknown)?;
+    let membership = GroupAssociation::fetch(tr, group_id, user_id)
+        .await
+        .log_error_msg(|| "error fetching group membership")?
+        .ok_or_else(unknown)?;
+    let role = GroupRole::fetch(tr, &membership.role)
+        .await
+        .log_error_msg(|| "error fetching group role")?;
+    Ok((group, membership, role))
+}
+
+fn group_permission_denied() -> async_graphql::Error {
+    AppError::Forbidden("not allowed by group role".into())
+        .extend()
+        .extend_with(|_e, ex| ex.set("key", "GROUP_PERMISSION_DENIED"))
+}
+
+fn last_group_admin() -> async_graphql::Error {
+    AppError::BadRequest("group needs another admin first".into())
+        .extend()
+        .extend_with(|_e, ex| ex.set("key", "LAST_GROUP_ADMIN"))
+}
+
+/// Change another member's role, for `promoteMember`/`demoteMember`.
+/// A group always keeps at least one admin
+async fn set_group_member_role(
+    ctx: &Context<'_>,
+    group_id: &str,
+    user_id: &str,
+    role: &str,
+) -> FieldResult<GroupAssociation> {
+    let user = ctx.data_unchecked::<User>();
+    let pool = ctx.data_unchecked::<PgPool>();
+    let group_id = group_id.parse::<i64>()?;
+    let member_id = user_id.parse::<i64>()?;
+    let mut tr = pool
+        .begin()
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error starting transaction")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+    let (group, _, caller_role) = fetch_group_membership(&mut tr, group_id, user.id)
+        .await
+        .extend_err(|_e, ex| ex.set("key", "UNKNOWN_GROUP"))?;
+    if !caller_role.can_manage {
+        return Err(group_permission_denied());
+    }
+    let member = GroupAssociation::fetch(&mut tr, group.id, member_id)
+        .await
+        .log_error_msg(|| "error fetching group member")
+        .extend()?
+        .ok_or_else(|| AppError::BadRequest(format!("user {member_id} is not a group member")))
+        .extend_err(|_e, ex| ex.set("key", "NOT_GROUP_MEMBER"))?;
+    if member.role == role {
+        return Ok(member);
+    }
+    if member.role == GroupRole::ADMIN
+        && GroupAssociation::count_with_role(&mut tr, group.id, GroupRole::ADMIN)
+            .await
+            .log_error_msg(|| "error counting group admins")
+            .extend()?
+            <= 1
+    {
+        return Err(last_group_admin());
+    }
+    let member = GroupAssociation::set_role(&mut tr, member.id, role)
+        .await
+        .log_error_msg(|| "error updating group role")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+    tr.commit()
+        .await
+        .map_err(AppError::from)
+        .log_error()
+        .extend()?;
+    Ok(member)
+}
+
 async fn create_user(
     tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
     handle: String,
@@ -365,9 +768,7 @@ async fn create_user(
         })?;
     }
 
-    // try to clean it up, also truncate the size in case
-    // people are being assholes
-    let phone_number = phone_number.trim().chars().take(20).collect::<String>();
+    let phone_number = crate::phone::normalize(phone_number).extend()?;
 
     let existing_phone: Option<Phone> = sqlx::query_as(
         r##"
@@ -393,8 +794,8 @@ async fn create_user(
 
     let phone: Option<Phone> = sqlx::query_as(
         r##"
-            insert into pin.phones (user_id, number)
-                values ($1, $2)
+            insert into pin.phones (user_id, number, number_hmac)
+                values ($1, $2, $3)
             on conflict (number)
                 where deleted is false and verified is not null
                 do nothing
@@ -402,7 +803,8 @@ async fn create_user(
         "##,
     )
     .bind(user.id)
-    .bind(phone_number)
+    .bind(&phone_number)
+    .bind(crate::phone::contact_hash(&phone_number))
     .fetch_optional(&mut *tr)
     .await
     .map_err(AppError::from)
@@ -439,6 +841,7 @@ impl MutationRoot {
         handle: String,
         phone_number: String,
         name: Option<String>,
+        #[graphql(desc = "Code from an invite link")] invite_code: Option<String>,
     ) -> FieldResult<User> {
         let pool = ctx.data_unchecked::<PgPool>();
         let mut tr = pool
@@ -448,6 +851,9 @@ impl MutationRoot {
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
 
         let user = create_user(&mut tr, handle, &phone_number, name).await?;
+        if let Some(code) = invite_code {
+            claim_invite(&mut tr, &code, user.id).await?;
+        }
         tr.commit().await.map_err(AppError::from).extend()?;
         send_verification_code(ctx, &user).await.extend()?;
         login_ctx(ctx, &user).await.extend()?;
@@ -497,6 +903,64 @@ impl MutationRoot {
         Ok(user)
     }
 
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Set the current user's timezone, an IANA name like `America/Los_Angeles`.
+    /// The question of the day rolls over at midnight in this timezone
+    async fn set_timezone(&self, ctx: &Context<'_>, timezone: String) -> FieldResult<User> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let (known,): (bool,) =
+            sqlx::query_as(r##"select exists(select 1 from pg_timezone_names where name = $1)"##)
+                .bind(&timezone)
+                .fetch_one(&mut *tr)
+                .await
+                .map_err(AppError::from)
+                .log_error_msg(|| "error checking timezone")
+                .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if !known {
+            return Err(AppError::BadRequest(format!("unknown timezone {timezone}"))
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "INVALID_TIMEZONE")));
+        }
+        let updated = sqlx::query(
+            r##"
+            update pin.profiles
+                set timezone = $2, modified = now()
+                where user_id = $1 and deleted is false
+            "##,
+        )
+        .bind(user.id)
+        .bind(&timezone)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error updating profile timezone")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if updated.rows_affected() == 0 {
+            sqlx::query(r##"insert into pin.profiles (user_id, timezone) values ($1, $2)"##)
+                .bind(user.id)
+                .bind(&timezone)
+                .execute(&mut *tr)
+                .await
+                .map_err(AppError::from)
+                .log_error_msg(|| "error inserting profile timezone")
+                .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        }
+        let user = User::fetch_user(&mut tr, user.id).await.extend()?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(user)
+    }
+
     /// Complete the login flow by sending the current user's phone number and
     /// the verification code that was received
     async fn login_phone_confirm(
@@ -527,6 +991,7 @@ impl MutationRoot {
             }
             Some(p) => p,
         };
+        let phone_number = crate::phone::normalize(&phone_number).extend()?;
         let user = User::fetch_user_by_number(&mut tr, &phone_number)
             .await
             .log_error_msg(|| "error fetching user by number")
@@ -552,13 +1017,14 @@ impl MutationRoot {
         })
     }
 
-    /// Initiate the verification flow for signup or login by sending the phone number
-    /// of the user's current device without providing a user handle
-    async fn login_phone(
+    /// Login with a password instead of a texted verification code. `handle_or_phone`
+    /// may be either the user's handle or their phone number
+    async fn login_password(
         &self,
         ctx: &Context<'_>,
-        #[graphql(desc = "The phone number of the device to login with")] phone_number: String,
-    ) -> FieldResult<bool> {
+        handle_or_phone: String,
+        password: String,
+    ) -> FieldResult<LoginSuccess> {
         let pool = ctx.data_unchecked::<PgPool>();
         let mut tr = pool
             .begin()
@@ -566,137 +1032,1531 @@ impl MutationRoot {
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let user = User::fetch_user_by_number(&mut tr, &phone_number)
+        let handle_or_phone = handle_or_phone.trim();
+        let user = match User::fetch_user_by_handle(&mut tr, handle_or_phone)
             .await
-            .log_error_msg(|| "error fetching user by number")?;
-        let user = match user {
-            Some(user) => user,
-            None => {
-                create_user(
-                    &mut tr,
-                    uuid::Uuid::new_v4().as_hyphenated().to_string(),
-                    &phone_number,
-                    None,
-                )
-                .await?
-            }
+            .log_error_msg(|| "error fetching user by handle")
+            .extend()?
+        {
+            Some(user) => Some(user),
+            None => match crate::phone::normalize(handle_or_phone) {
+                Ok(phone_number) => User::fetch_user_by_number(&mut tr, &phone_number)
+                    .await
+                    .log_error_msg(|| "error fetching user by number")
+                    .extend()?,
+                Err(_) => None,
+            },
         };
         tr.commit().await.map_err(AppError::from).extend()?;
-        send_verification_code(ctx, &user)
-            .await
-            .log_error_msg(|| "error sending verification code")
-            .extend()?;
-        challenge_phone_ctx(ctx, &phone_number).await.extend()?;
-        Ok(true)
-    }
-
-    /// Remove the current authentication cookie
-    async fn logout(&self, ctx: &Context<'_>) -> bool {
-        // todo: mark pin.auth_tokens deleted for current user
-        let cookie_str = format_set_auth_cookie(&generate_clear_token());
-        ctx.append_http_header("set-cookie", cookie_str);
-        true
-    }
 
-    #[graphql(guard = "LoginNeedsVerificationGuard::new()")]
-    /// Initiate phone verification. This will send a text to the current user's phone number
-    async fn send_verification_code(&self, ctx: &Context<'_>) -> FieldResult<User> {
-        let user = ctx.data_unchecked::<User>();
-        send_verification_code(ctx, user).await.extend()?;
-        Ok(user.clone())
+        verify_user_password(pool, user.as_ref().map(|u| u.id), &password).await?;
+        let user = user.ok_or_else(invalid_credentials)?;
+        let token = login_ctx(ctx, &user).await.extend()?;
+        Ok(LoginSuccess {
+            auth_token: token,
+            user,
+        })
     }
 
-    #[graphql(guard = "LoginNeedsVerificationGuard::new()")]
-    /// Send a valid verification code to verify the current user's phone number
-    async fn verify_number(&self, ctx: &Context<'_>, code: String) -> FieldResult<User> {
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Set a password for the current user so they can login without a verification code.
+    /// Use change_password if a password is already set
+    async fn set_password(&self, ctx: &Context<'_>, password: String) -> FieldResult<bool> {
         let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
+        validate_new_password(&password)?;
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let user_id = user.id;
-        let user = _verify_code_for_user(&mut tr, user, &code)
+        let existing = Password::fetch_for_user(&mut tr, user.id)
             .await
-            .log_error_msg(|| format!("failed verifying code for user {user_id}"))?;
+            .log_error_msg(|| "error loading existing password")
+            .extend()?;
+        if existing.is_some() {
+            return Err(AppError::BadRequest("password already set".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "PASSWORD_ALREADY_SET")));
+        }
+        let (salt, hash) = hash_password(&password).extend()?;
+        Password::set_for_user(&mut tr, user.id, &salt, &hash)
+            .await
+            .log_error_msg(|| "error saving password")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         tr.commit().await.map_err(AppError::from).extend()?;
-        Ok(user)
+        Ok(true)
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Decom the current account. This requires passing a valid verification code initiated
-    /// by send_verification_code
-    async fn delete_account(&self, ctx: &Context<'_>, code: String) -> FieldResult<bool> {
-        let user = ctx.data_unchecked::<User>();
-        let pool = ctx.data_unchecked::<PgPool>();
+    /// Change the current user's password. Requires the current password
+    async fn change_password(
+        &self,
+        ctx: &Context<'_>,
+        current_password: String,
+        new_password: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        validate_new_password(&new_password)?;
+        verify_user_password(pool, Some(user.id), &current_password).await?;
+
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let (salt, hash) = hash_password(&new_password).extend()?;
+        Password::set_for_user(&mut tr, user.id, &salt, &hash)
+            .await
+            .log_error_msg(|| "error saving password")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit().await.map_err(AppError::from).extend()?;
+        Ok(true)
+    }
+
+    /// Initiate the verification flow for signup or login by sending the phone number
+    /// of the user's current device without providing a user handle
+    async fn login_phone(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "The phone number of the device to login with")] phone_number: String,
+        #[graphql(desc = "Code from an invite link")] invite_code: Option<String>,
+    ) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let phone_number = crate::phone::normalize(&phone_number).extend()?;
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let user = User::fetch_user_by_number(&mut tr, &phone_number)
+            .await
+            .log_error_msg(|| "error fetching user by number")?;
+        let (user, created) = match user {
+            Some(user) => (user, false),
+            None => {
+                let user = create_user(
+                    &mut tr,
+                    uuid::Uuid::new_v4().as_hyphenated().to_string(),
+                    &phone_number,
+                    None,
+                )
+                .await?;
+                (user, true)
+            }
+        };
+        // nobody has proven they own the number yet, so invites can only be
+        // claimed by the account this call creates
+        if let (Some(code), true) = (invite_code, created) {
+            claim_invite(&mut tr, &code, user.id).await?;
+        }
+        tr.commit().await.map_err(AppError::from).extend()?;
+        send_verification_code(ctx, &user)
+            .await
+            .log_error_msg(|| "error sending verification code")
+            .extend()?;
+        challenge_phone_ctx(ctx, &phone_number).await.extend()?;
+        Ok(true)
+    }
+
+    /// Remove the current authentication cookie and revoke its session
+    async fn logout(&self, ctx: &Context<'_>) -> FieldResult<bool> {
+        if let Some(session) = ctx.data_opt::<CurrentSession>() {
+            let pool = ctx.data_unchecked::<PgPool>();
+            sqlx::query(
+                r##"
+                update pin.auth_tokens
+                    set deleted = true, modified = now()
+                    where id = $1
+                "##,
+            )
+            .bind(session.id)
+            .execute(pool)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error revoking session on logout")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        }
+        let cookie_str = format_set_auth_cookie(&generate_clear_token());
+        ctx.append_http_header("set-cookie", cookie_str);
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Revoke one of the current user's sessions. Revoking the current
+    /// session also clears the authentication cookie
+    async fn revoke_session(&self, ctx: &Context<'_>, session_id: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let session_id = session_id.parse::<i64>()?;
+        let revoked: Option<(i64,)> = sqlx::query_as(
+            r##"
+            update pin.auth_tokens
+                set deleted = true, modified = now()
+                where id = $1
+                    and user_id = $2
+                    and deleted is false
+                returning id
+            "##,
+        )
+        .bind(session_id)
+        .bind(user.id)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error revoking session")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if revoked.is_none() {
+            return Err(AppError::BadRequest("bad request".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "UNKNOWN_SESSION")));
+        }
+        let is_current = ctx
+            .data_opt::<CurrentSession>()
+            .map(|s| s.id == session_id)
+            .unwrap_or(false);
+        if is_current {
+            let cookie_str = format_set_auth_cookie(&generate_clear_token());
+            ctx.append_http_header("set-cookie", cookie_str);
+        }
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Revoke every session of the current user except the one making this request.
+    /// Returns the number of sessions revoked
+    async fn revoke_all_other_sessions(&self, ctx: &Context<'_>) -> FieldResult<i64> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let current_id = ctx.data_opt::<CurrentSession>().map(|s| s.id);
+        let res = sqlx::query(
+            r##"
+            update pin.auth_tokens
+                set deleted = true, modified = now()
+                where user_id = $1
+                    and deleted is false
+                    and id is distinct from $2
+            "##,
+        )
+        .bind(user.id)
+        .bind(current_id)
+        .execute(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error revoking other sessions")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(res.rows_affected() as i64)
+    }
+
+    #[graphql(guard = "LoginNeedsVerificationGuard::new()")]
+    /// Initiate phone verification. This will send a text to the current user's phone number
+    async fn send_verification_code(&self, ctx: &Context<'_>) -> FieldResult<User> {
+        let user = ctx.data_unchecked::<User>();
+        send_verification_code(ctx, user).await.extend()?;
+        Ok(user.clone())
+    }
+
+    #[graphql(guard = "LoginNeedsVerificationGuard::new()")]
+    /// Send a valid verification code to verify the current user's phone number
+    async fn verify_number(&self, ctx: &Context<'_>, code: String) -> FieldResult<User> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let user_id = user.id;
+        let user = _verify_code_for_user(&mut tr, user, &code)
+            .await
+            .log_error_msg(|| format!("failed verifying code for user {user_id}"))?;
+        tr.commit().await.map_err(AppError::from).extend()?;
+        Ok(user)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Start changing the current user's phone number. A verification code is sent
+    /// to the new number, which must be confirmed with confirm_phone_change. The current
+    /// number keeps working until then.
+    async fn request_phone_change(
+        &self,
+        ctx: &Context<'_>,
+        phone_number: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let phone_number = crate::phone::normalize(&phone_number).extend()?;
+        if phone_number == user.phone_number {
+            return Err(AppError::BadRequest("bad request".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "SAME_PHONE")));
+        }
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let existing_phone: Option<Phone> = sqlx::query_as(
+            r##"
+            select * from pin.phones
+            where number = $1
+                and deleted is false
+                and verified is not null
+            "##,
+        )
+        .bind(&phone_number)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error checking for existing phone")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if existing_phone.is_some() {
+            return Err(AppError::BadRequest("bad request".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "UNAVAILABLE_PHONE")));
+        }
+
+        // only the most recent request is kept around
+        let previous: Option<Phone> = sqlx::query_as(
+            r##"
+            update pin.phones
+                set deleted = true, modified = now()
+                where user_id = $1 and deleted is false and pending is true
+                returning *
+            "##,
+        )
+        .bind(user.id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error clearing pending phone")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let pending: Phone = sqlx::query_as(
+            r##"
+            insert into pin.phones (user_id, number, number_hmac, pending)
+                values ($1, $2, $3, true)
+                returning *
+            "##,
+        )
+        .bind(user.id)
+        .bind(&phone_number)
+        .bind(crate::phone::contact_hash(&phone_number))
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error creating pending phone")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit().await.map_err(AppError::from).extend()?;
+
+        send_code_to_phone(
+            ctx,
+            user.id,
+            &pending.number,
+            previous.and_then(|p| p.verification_sent),
+            Some(pending.id),
+        )
+        .await
+        .log_error_msg(|| "error sending phone change code")
+        .extend()?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Finish changing the current user's phone number with the code that was
+    /// sent to the new number. The old number is released.
+    async fn confirm_phone_change(&self, ctx: &Context<'_>, code: String) -> FieldResult<User> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let pending: Option<Phone> = sqlx::query_as(
+            r##"
+            select * from pin.phones
+            where user_id = $1 and deleted is false and pending is true
+            "##,
+        )
+        .bind(user.id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error loading pending phone")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let pending = match pending {
+            None => {
+                return Err(AppError::BadRequest("bad request".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "NO_PENDING_PHONE_CHANGE")))
+            }
+            Some(p) => p,
+        };
+        let user_id = user.id;
+        _check_latest_code(&mut tr, user.id, Some(pending.id), &code)
+            .await
+            .log_error_msg(|| format!("failed verifying phone change code for user {user_id}"))
+            .extend()?;
+
+        sqlx::query(
+            r##"
+            update pin.phones
+                set deleted = true, modified = now()
+                where user_id = $1 and deleted is false and pending is false
+            "##,
+        )
+        .bind(user.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error releasing old phone")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        sqlx::query(
+            r##"
+            update pin.phones
+                set pending = false, verified = now(), modified = now()
+                where id = $1
+            "##,
+        )
+        .bind(pending.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .extend_err(|e, ex| {
+            if let Some((_code, _constraint)) = e.unique_constraint_error() {
+                tracing::info!("phone {} was verified by another user", &pending.number);
+                ex.set("key", "UNAVAILABLE_PHONE")
+            } else {
+                tracing::error!("error verifying new phone {:?}", e);
+                ex.set("key", "DATABASE_ERROR");
+            }
+        })?;
+        let user = User::fetch_user(&mut tr, user.id).await.extend()?;
+        tr.commit().await.map_err(AppError::from).extend()?;
+        Ok(user)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Decom the current account. This requires passing a valid verification code initiated
+    /// by send_verification_code
+    async fn delete_account(&self, ctx: &Context<'_>, code: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let user_id = user.id;
+        let user = _verify_code_for_user(&mut tr, user, &code)
+            .await
+            .log_error_msg(|| format!("failed verifying code for user {user_id}"))?;
+        tr.commit().await.map_err(AppError::from).extend()?;
+
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        sqlx::query(
+            r##"
+            update pin.phones
+                set modified = now(),
+                deleted = true
+            where user_id = $1
+        "##,
+        )
+        .bind(user.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error()
+        .extend()?;
+
+        sqlx::query(
+            r##"
+            update pin.users
+                set modified = now(),
+                deleted = true
+            where id = $1
+        "##,
+        )
+        .bind(user.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error()
+        .extend()?;
+
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        let r = self.logout(ctx).await?;
+        Ok(r)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Submit an opinion for a specific question_id. Give exactly one answer
+    /// argument, matching the question's kind. Rankings list every option id,
+    /// most preferred first. Only today's question of the day, or those of the
+    /// last `ANSWER_WINDOW_DAYS` days, can be answered
+    async fn opine(
+        &self,
+        ctx: &Context<'_>,
+        question_id: String,
+        multi_selection_id: Option<String>,
+        scale_value: Option<i64>,
+        text: Option<String>,
+        ranking_ids: Option<Vec<String>>,
+    ) -> FieldResult<Pinion> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let answer = match (multi_selection_id, scale_value, text, ranking_ids) {
+            (Some(id), None, None, None) => Answer::Multi(id.parse::<i64>()?),
+            (None, Some(value), None, None) => Answer::Scale(value),
+            (None, None, Some(text), None) => Answer::Text(text),
+            (None, None, None, Some(ids)) => Answer::Ranking(
+                ids.iter()
+                    .map(|id| id.parse::<i64>())
+                    .collect::<std::result::Result<_, _>>()?,
+            ),
+            _ => {
+                return Err(AppError::BadRequest("give exactly one answer".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "INVALID_ANSWER")))
+            }
+        };
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let q_id = question_id.parse::<i64>()?;
+        let unknown_question = || {
+            AppError::BadRequest(format!("unknown question {q_id}"))
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "UNKNOWN_QUESTION"))
+        };
+        // loaded with deleted questions so they get their own error
+        let question = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(QuestionId(q_id))
+            .await?
+            .ok_or_else(unknown_question)?;
+        if let Some(group_id) = question.group_id {
+            if GroupAssociation::fetch(&mut tr, group_id, user.id)
+                .await
+                .log_error_msg(|| "error fetching group membership")
+                .extend()?
+                .is_none()
+            {
+                return Err(unknown_question());
+            }
+        }
+        if question.deleted {
+            return Err(AppError::BadRequest(format!("question {q_id} was deleted"))
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "QUESTION_DELETED")));
+        }
+        let timezone = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(ProfileForUserId(user.id))
+            .await?
+            .and_then(|p| p.timezone);
+        let unanswerable = question
+            .check_answerable(&mut tr, timezone.as_deref())
+            .await
+            .log_error_msg(|| "error checking question schedule")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if let Some(reason) = unanswerable {
+            return Err(AppError::BadRequest(reason.message().into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", reason.key())));
+        }
+        let answer = question
+            .validate_answer(&mut tr, answer)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "INVALID_ANSWER"))?;
+        if let Answer::Multi(option_id) = answer {
+            if !question
+                .has_option(&mut tr, option_id)
+                .await
+                .log_error_msg(|| "error fetching question options")
+                .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?
+            {
+                return Err(AppError::BadRequest(format!(
+                    "option {option_id} isn't an option of question {q_id}"
+                ))
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "INVALID_OPTION")));
+            }
+        }
+        let current = Pinion::fetch_current(&mut tr, user.id, q_id)
+            .await
+            .log_error_msg(|| "error fetching current pinion")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if let Some((pinion, first_answered)) = current {
+            if pinion.to_answer() == answer {
+                return Ok(pinion);
+            }
+            if let Some(lock_time) = Pinion::lock_time(first_answered) {
+                if lock_time <= Utc::now() {
+                    return Err(AppError::BadRequest(format!(
+                        "answers to question {q_id} are final"
+                    ))
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "PINION_LOCKED")));
+                }
+            }
+        }
+        sqlx::query(
+            r##"
+            update pin.pinions
+                set deleted = true, modified = now()
+                where user_id = $1 and question_id = $2 and deleted is false
+            "##,
+        )
+        .bind(user.id)
+        .bind(q_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .extend_err(|_e, ex| {
+            ex.set("key", "DATABASE_ERROR");
+        })?;
+        let pinion = Pinion::insert(&mut tr, user.id, q_id, &answer)
+            .await
+            .extend_err(|e, ex| {
+                // changes are serialized by locking the current answer, so this
+                // is a first answer racing another request for the same question
+                let constraint = e.unique_constraint_error().map(|(_code, c)| c);
+                if constraint.as_deref() == Some("idx_pinions_unique_user_question") {
+                    tracing::info!("{} submitted concurrent pinions", &user.handle);
+                    ex.set("key", "CONCURRENT_RESPONSE");
+                } else {
+                    tracing::error!("error saving pinion {:?}", e);
+                    ex.set("key", "DATABASE_ERROR");
+                }
+            })?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(pinion)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Submit a comment for a pinion
+    async fn comment(
+        &self,
+        ctx: &Context<'_>,
+        pinion_id: String,
+        content: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let pinion_id = pinion_id.parse::<i64>()?;
+        sqlx::query(
+            r##"
+            insert into pin.comments
+                (pinion_id, user_id, content)
+                values ($1, $2, $3)
+            "##,
+        )
+        .bind(pinion_id)
+        .bind(user.id)
+        .bind(content)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .extend_err(|_e, ex| {
+            ex.set("key", "DATABASE_ERROR");
+        })?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Accept a friend request sent to the current user
+    async fn accept_fiend(
+        &self,
+        ctx: &Context<'_>,
+        relationship_id: String,
+    ) -> FieldResult<Friend> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let relationship_id = relationship_id.parse::<i64>()?;
+        let f = fetch_pending_friend_request(&mut tr, relationship_id, user.id, true).await?;
+        let f: Friend = sqlx::query_as(
+            r#"
+            update pin.friends
+                set accepted = now(), modified = now()
+                where id = $1
+                returning *
+            "#,
+        )
+        .bind(f.id)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error accepting friend request")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(f)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Decline a friend request sent to the current user
+    async fn decline_friend_request(
+        &self,
+        ctx: &Context<'_>,
+        relationship_id: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let relationship_id = relationship_id.parse::<i64>()?;
+        let f = fetch_pending_friend_request(&mut tr, relationship_id, user.id, true).await?;
+        Friend::delete(&mut tr, f.id)
+            .await
+            .log_error_msg(|| "error declining friend request")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Withdraw a friend request the current user sent
+    async fn cancel_friend_request(
+        &self,
+        ctx: &Context<'_>,
+        relationship_id: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let relationship_id = relationship_id.parse::<i64>()?;
+        let f = fetch_pending_friend_request(&mut tr, relationship_id, user.id, false).await?;
+        Friend::delete(&mut tr, f.id)
+            .await
+            .log_error_msg(|| "error cancelling friend request")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Request a friendship
+    async fn request_friend(&self, ctx: &Context<'_>, phone_number: String) -> FieldResult<Friend> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let phone_number = crate::phone::normalize(&phone_number).extend()?;
+        let other_user = User::fetch_user_by_number(&mut tr, &phone_number)
+            .await
+            .log_error_msg(|| "error querying other user by phone number")
+            .extend()?
+            .ok_or_else(unknown_user)?;
+        check_not_blocked(pool, user.id, other_user.id).await?;
+        let f: Friend = sqlx::query_as(
+            r#"
+            insert into pin.friends
+                (requestor_id, acceptor_id)
+                values ($1, $2)
+                returning *
+            "#,
+        )
+        .bind(user.id)
+        .bind(other_user.id)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .extend_err(|e, ex| {
+            if let Some((_code, _constraint)) = e.unique_constraint_error() {
+                tracing::info!(
+                    "{}:{} friend relationship already exists",
+                    &user.handle,
+                    &other_user.handle
+                );
+                ex.set("key", "DUPLICATE_FRIEND_REQUEST");
+            } else {
+                tracing::error!("error creating friend relationship {:?}", e);
+                ex.set("key", "DATABASE_ERROR");
+            }
+        })?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(f)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Request a friendship by other user's ID
+    async fn request_friend_id(&self, ctx: &Context<'_>, user_id: String) -> FieldResult<Friend> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let other_user_id = user_id.parse::<i64>()?;
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let other_user = match User::fetch_user(&mut tr, other_user_id).await {
+            Err(AppError::DBNotFound(_)) => return Err(unknown_user()),
+            r => r.log_error_msg(|| "unable to load other user")?,
+        };
+        check_not_blocked(pool, user.id, other_user.id).await?;
+        let f: Friend = sqlx::query_as(
+            r#"
+            insert into pin.friends
+                (requestor_id, acceptor_id)
+                values ($1, $2)
+                returning *
+            "#,
+        )
+        .bind(user.id)
+        .bind(other_user_id)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .extend_err(|e, ex| {
+            if let Some((_code, _constraint)) = e.unique_constraint_error() {
+                tracing::info!(
+                    "{}:{} friend relationship already exists",
+                    &user.handle,
+                    &other_user.handle
+                );
+                ex.set("key", "DUPLICATE_FRIEND_REQUEST");
+            } else {
+                tracing::error!("error creating friend relationship {:?}", e);
+                ex.set("key", "DATABASE_ERROR");
+            }
+        })?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(f)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Terminate a friendship
+    async fn delete_fiend(
+        &self,
+        ctx: &Context<'_>,
+        relationship_id: String,
+    ) -> FieldResult<Friend> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let relationship_id = relationship_id.parse::<i64>()?;
+        let f = Friend::fetch_for_update(&mut tr, relationship_id)
+            .await
+            .log_error_msg(|| "error fetching friendship")
+            .extend()?
+            .filter(|f| user.id == f.requestor_id || user.id == f.acceptor_id)
+            .ok_or_else(|| AppError::BadRequest("User not related to friendship".into()))
+            .extend()?;
+        let f = Friend::delete(&mut tr, f.id)
+            .await
+            .log_error_msg(|| "error deleting friendship")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(f)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Block another user. Ends any friendship or pending request between the two,
+    /// and hides each user's content and requests from the other
+    async fn block_user(&self, ctx: &Context<'_>, user_id: String) -> FieldResult<Block> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let blocked_id = user_id.parse::<i64>()?;
+        if blocked_id == user.id {
+            return Err(AppError::BadRequest("can't block yourself".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "CANNOT_BLOCK_SELF")));
+        }
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let other_user = User::fetch_user(&mut tr, blocked_id)
+            .await
+            .log_error_msg(|| "unable to load user to block")
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_USER"))?;
+        let existing: Option<Block> = sqlx::query_as(
+            r##"
+            select * from pin.blocks
+                where blocker_id = $1 and blocked_id = $2 and deleted is false
+            "##,
+        )
+        .bind(user.id)
+        .bind(other_user.id)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error fetching block")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let block = match existing {
+            Some(block) => block,
+            None => sqlx::query_as(
+                r##"
+                insert into pin.blocks
+                    (blocker_id, blocked_id)
+                    values ($1, $2)
+                    returning *
+                "##,
+            )
+            .bind(user.id)
+            .bind(other_user.id)
+            .fetch_one(&mut *tr)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error creating block")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?,
+        };
+        sqlx::query(
+            r##"
+            update pin.friends
+                set deleted = true, modified = now()
+                where deleted is false
+                    and (
+                        (requestor_id = $1 and acceptor_id = $2)
+                        or (requestor_id = $2 and acceptor_id = $1)
+                    )
+            "##,
+        )
+        .bind(user.id)
+        .bind(other_user.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error ending friendship with blocked user")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(block)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Remove a block. Friendships ended by the block aren't restored
+    async fn unblock_user(&self, ctx: &Context<'_>, user_id: String) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let blocked_id = user_id.parse::<i64>()?;
+        let res = sqlx::query(
+            r##"
+            update pin.blocks
+                set deleted = true, modified = now()
+                where blocker_id = $1 and blocked_id = $2 and deleted is false
+            "##,
+        )
+        .bind(user.id)
+        .bind(blocked_id)
+        .execute(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error removing block")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Check if phone numbers are associated with signed up users. Numbers are
+    /// returned as given, numbers that can't be parsed or that belong to blocked
+    /// users are reported as not signed up
+    async fn check_phones(
+        &self,
+        ctx: &Context<'_>,
+        phone_numbers: Vec<String>,
+    ) -> FieldResult<Vec<PhoneCheck>> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let normalized = phone_numbers
+            .iter()
+            .map(|n| crate::phone::normalize(n).ok())
+            .collect::<Vec<_>>();
+        let checks: Vec<PhoneCheck> = sqlx::query_as(
+            r#"
+            select t.in_num as number, t.normalized, p.number is not null as signed_up
+            from unnest($1::text[], $2::text[]) as t(in_num, normalized)
+            left outer join pin.phones p
+                on p.number = t.normalized and p.deleted is false and p.pending is false
+                and not pin.is_blocked($3, p.user_id);
+            "#,
+        )
+        .bind(&phone_numbers)
+        .bind(&normalized)
+        .bind(user.id)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "failed querying for phones")
+        .extend()?;
+        // remember which contacts are signed up for friend suggestions
+        sqlx::query(
+            r#"
+            insert into pin.contacts (user_id, contact_user_id)
+            select distinct $1::bigint, p.user_id
+            from pin.phones p
+            where p.number = any($2)
+                and p.deleted is false
+                and p.pending is false
+                and p.user_id != $1
+            on conflict (user_id, contact_user_id) where deleted is false do nothing
+            "#,
+        )
+        .bind(user.id)
+        .bind(&normalized)
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "failed saving contacts")
+        .extend()?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(checks)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Text an invite link to a contact who hasn't signed up. They're friended with
+    /// the current user when they sign up with the number or from the link
+    async fn invite_contact(&self, ctx: &Context<'_>, phone_number: String) -> FieldResult<Invite> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let phone_number = crate::phone::normalize(&phone_number).extend()?;
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        // serialize the inviter's invites so concurrent calls can't exceed the daily limit
+        sqlx::query(r##"select 1 from pin.users where id = $1 for update"##)
+            .bind(user.id)
+            .execute(&mut *tr)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error locking inviter")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+
+        #[derive(sqlx::FromRow)]
+        struct InviteCheck {
+            signed_up: bool,
+            blocked: bool,
+            sent_today: i64,
+            number_texted: bool,
+        }
+        // users on either side of a block look like they haven't signed up,
+        // like in checkPhones, but aren't texted
+        let check: InviteCheck = sqlx::query_as(
+            r##"
+            select
+                exists(
+                    select 1 from pin.phones
+                    where number = $2
+                        and deleted is false
+                        and pending is false
+                        and verified is not null
+                        and not pin.is_blocked($1, user_id)
+                ) as signed_up,
+                exists(
+                    select 1 from pin.phones
+                    where number = $2
+                        and deleted is false
+                        and pending is false
+                        and verified is not null
+                        and pin.is_blocked($1, user_id)
+                ) as blocked,
+                (
+                    select count(*) from pin.invites
+                    where inviter_id = $1 and created > now() - interval '1 day'
+                ) as sent_today,
+                exists(
+                    select 1 from pin.invites
+                    where number = $2 and sent > now() - interval '1 day'
+                ) as number_texted
+            "##,
+        )
+        .bind(user.id)
+        .bind(&phone_number)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error checking invite limits")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if check.signed_up {
+            return Err(
+                AppError::BadRequest("number already belongs to a user".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "ALREADY_SIGNED_UP")),
+            );
+        }
+        if check.sent_today >= CONFIG.invite_daily_limit {
+            return Err(AppError::BadRequest("too many invites".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "TOO_MANY_INVITES")));
+        }
+        // only text a number once a day however many people invite it
+        let invite: Option<Invite> = sqlx::query_as(
+            r##"
+            insert into pin.invites (inviter_id, number, sent)
+                values ($1, $2, case when $3 then null else now() end)
+            on conflict (inviter_id, number)
+                where deleted is false
+                do nothing
+            returning *
+            "##,
+        )
+        .bind(user.id)
+        .bind(&phone_number)
+        .bind(check.number_texted || check.blocked)
+        .fetch_optional(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error creating invite")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let invite = invite.ok_or_else(|| {
+            AppError::BadRequest("number was already invited".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "DUPLICATE_INVITE"))
+        })?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        if invite.sent.is_some() {
+            // handles of users that signed up by phone are generated, prefer their name
+            let name = ctx
+                .data_unchecked::<AppLoader>()
+                .load_one(ProfileForUserId(user.id))
+                .await?
+                .and_then(|p| p.name)
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or_else(|| user.handle.clone());
+            let body = format!(
+                "{} invited you to Pinion! {}?code={}",
+                name,
+                CONFIG.invite_url,
+                invite.code()
+            );
+            send_sms(ctx, &phone_number, &body)
+                .await
+                .log_error_msg(|| "error sending invite")
+                .extend()?;
+        }
+        Ok(invite)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Like `checkPhones`, but with contacts hashed on the device so numbers that
+    /// don't belong to a user are never sent. Each hash is the hex HMAC-SHA256 of an
+    /// E.164 number keyed with `contactHashKey`. Only matching hashes are returned
+    async fn check_phone_hashes(
+        &self,
+        ctx: &Context<'_>,
+        hashes: Vec<String>,
+    ) -> FieldResult<Vec<PhoneHashMatch>> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        if hashes.len() > MAX_CONTACT_HASHES {
+            return Err(AppError::BadRequest(format!(
+                "at most {MAX_CONTACT_HASHES} hashes can be checked at once"
+            ))
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "TOO_MANY_HASHES")));
+        }
+        let hashes = hashes
+            .iter()
+            .map(|h| h.trim().to_lowercase())
+            .collect::<Vec<_>>();
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let user_id = user.id;
-        let user = _verify_code_for_user(&mut tr, user, &code)
+        let matches: Vec<PhoneHashMatch> = sqlx::query_as(
+            r#"
+            select distinct on (t.hash) t.hash, u.id, u.handle,
+            exists(select f.* from pin.friends f where
+                accepted is not null
+                and deleted is false
+                and (
+                    (acceptor_id = $2 and requestor_id = u.id)
+                    or
+                    (acceptor_id = u.id and requestor_id = $2)
+                )
+            ) as is_friend
+            from unnest($1::text[]) as t(hash)
+            inner join pin.phones p
+                on p.number_hmac = t.hash and p.deleted is false and p.pending is false
+            inner join pin.users u on u.id = p.user_id and u.deleted is false
+            where u.id != $2
+                and not pin.is_blocked($2, u.id)
+            order by t.hash
+            "#,
+        )
+        .bind(&hashes)
+        .bind(user.id)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "failed querying for phone hashes")
+        .extend()?;
+        // remember which contacts are signed up for friend suggestions
+        sqlx::query(
+            r#"
+            insert into pin.contacts (user_id, contact_user_id)
+            select $1, unnest($2::bigint[])
+            on conflict (user_id, contact_user_id) where deleted is false do nothing
+            "#,
+        )
+        .bind(user.id)
+        .bind(matches.iter().map(|m| m.id).collect::<Vec<_>>())
+        .execute(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "failed saving contacts")
+        .extend()?;
+        tr.commit()
             .await
-            .log_error_msg(|| format!("failed verifying code for user {user_id}"))?;
-        tr.commit().await.map_err(AppError::from).extend()?;
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(matches)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    /// Create a new question. It's added to the end of the question queue
+    /// unless a `priority` is given. `kind` defaults to multi (multiple choice),
+    /// and can also be yesno, ranking, scale or text
+    #[allow(clippy::too_many_arguments)]
+    async fn create_question(
+        &self,
+        ctx: &Context<'_>,
+        prompt: String,
+        #[graphql(default)] options: Vec<String>,
+        priority: Option<i64>,
+        kind: Option<String>,
+        scale_min: Option<i64>,
+        scale_max: Option<i64>,
+        scale_step: Option<i64>,
+    ) -> FieldResult<Question> {
+        let new = new_question(prompt, options, kind, scale_min, scale_max, scale_step)
+            .and_then(NewQuestion::validate)
+            .extend()?;
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let question = Question::create(&mut tr, &new, priority, None)
+            .await
+            .log_error_msg(|| "error creating question")
+            .extend()?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        refill_schedule(pool).await;
+        Ok(question)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    /// Edit a question's prompt and/or replace its options. Options can't
+    /// be changed once anyone has answered the question
+    async fn update_question(
+        &self,
+        ctx: &Context<'_>,
+        question_id: String,
+        prompt: Option<String>,
+        options: Option<Vec<String>>,
+    ) -> FieldResult<Question> {
+        let prompt = prompt
+            .map(|p| NewQuestion::validate_prompt(&p))
+            .transpose()
+            .extend()?;
+        let options = options
+            .map(|o| NewQuestion::validate_options(&o))
+            .transpose()
+            .extend()?;
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let question = fetch_question_for_admin(&mut tr, question_id.parse::<i64>()?)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_QUESTION"))?;
+        if let Some(options) = options {
+            if !question.has_options() {
+                return Err(AppError::BadRequest(format!(
+                    "{} questions don't have options",
+                    question.kind
+                ))
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "QUESTION_HAS_NO_OPTIONS")));
+            }
+            if Question::has_pinions(&mut tr, question.id).await.extend()? {
+                return Err(AppError::BadRequest("question has responses".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "QUESTION_HAS_RESPONSES")));
+            }
+            Question::replace_options(&mut tr, question.id, &options)
+                .await
+                .log_error_msg(|| "error replacing question options")
+                .extend()?;
+        }
+        let question: Question = sqlx::query_as(
+            r##"
+            update pin.questions
+                set prompt = coalesce($2, prompt), modified = now()
+                where id = $1
+                returning *
+            "##,
+        )
+        .bind(question.id)
+        .bind(prompt)
+        .fetch_one(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error updating question")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(question)
+    }
 
+    #[graphql(guard = "AdminGuard::new()")]
+    /// Delete a question that hasn't been used as a question of the day yet,
+    /// and isn't scheduled on a day that may already be live
+    async fn delete_question(&self, ctx: &Context<'_>, question_id: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<PgPool>();
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let question = fetch_question_for_admin(&mut tr, question_id.parse::<i64>()?)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_QUESTION"))?;
+        if question.used.is_some() {
+            return Err(AppError::BadRequest("question already used".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "QUESTION_ALREADY_USED")));
+        }
+        let today = crate::schedule::today(&mut tr)
+            .await
+            .log_error_msg(|| "error loading schedule date")
+            .extend()?;
+        check_question_not_live(&mut tr, question.id, today).await?;
         sqlx::query(
             r##"
-            update pin.phones
-                set modified = now(),
-                deleted = true
-            where user_id = $1
-        "##,
+            update pin.questions
+                set deleted = true, modified = now()
+                where id = $1
+            "##,
         )
-        .bind(user.id)
+        .bind(question.id)
         .execute(&mut *tr)
         .await
         .map_err(AppError::from)
-        .log_error()
-        .extend()?;
-
+        .log_error_msg(|| "error deleting question")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         sqlx::query(
             r##"
-            update pin.users
-                set modified = now(),
-                deleted = true
-            where id = $1
-        "##,
+            update pin.question_schedule
+                set deleted = true, modified = now()
+                where question_id = $1 and deleted is false
+            "##,
         )
-        .bind(user.id)
+        .bind(question.id)
         .execute(&mut *tr)
         .await
         .map_err(AppError::from)
-        .log_error()
-        .extend()?;
+        .log_error_msg(|| "error unscheduling deleted question")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        refill_schedule(pool).await;
+        Ok(true)
+    }
 
+    #[graphql(guard = "AdminGuard::new()")]
+    /// Reorder unused questions. The priorities currently held by the given
+    /// questions are reassigned so they come up in the order given
+    async fn reorder_questions(
+        &self,
+        ctx: &Context<'_>,
+        question_ids: Vec<String>,
+    ) -> FieldResult<Vec<Question>> {
+        let ids = question_ids
+            .iter()
+            .map(|id| id.parse::<i64>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let questions: Vec<Question> = sqlx::query_as(
+            r##"
+            select * from pin.questions
+                where id = any($1) and deleted is false and used is null and group_id is null
+                for update
+            "##,
+        )
+        .bind(&ids)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error fetching questions to reorder")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if questions.len() != ids.len() {
+            return Err(
+                AppError::BadRequest("questions must be unique, unused questions".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "UNKNOWN_QUESTION")),
+            );
+        }
+        let mut priorities = questions.iter().map(|q| q.priority).collect::<Vec<_>>();
+        priorities.sort_unstable();
+        let questions: Vec<Question> = sqlx::query_as(
+            r##"
+            update pin.questions q
+                set priority = t.priority, modified = now()
+                from unnest($1::bigint[], $2::bigint[]) as t(id, priority)
+                where q.id = t.id
+                returning q.*
+            "##,
+        )
+        .bind(&ids)
+        .bind(&priorities)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "error reordering questions")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        let r = self.logout(ctx).await?;
-        Ok(r)
+        refill_schedule(pool).await;
+        let mut questions = questions;
+        questions.sort_by_key(|q| q.priority);
+        Ok(questions)
     }
 
-    #[graphql(guard = "LoginGuard::new()")]
-    /// Submit an opinion for a specific question_id
-    async fn opine(
+    #[graphql(guard = "AdminGuard::new()")]
+    /// Schedule an unused question as the question of the day on a date after tomorrow,
+    /// replacing whatever was scheduled for that day
+    async fn schedule_question(
         &self,
         ctx: &Context<'_>,
         question_id: String,
-        multi_selection_id: String,
-    ) -> FieldResult<Pinion> {
-        let user = ctx.data_unchecked::<User>();
+        date: NaiveDate,
+    ) -> FieldResult<ScheduledQuestion> {
         let pool = ctx.data_unchecked::<PgPool>();
         let mut tr = pool
             .begin()
@@ -704,166 +2564,290 @@ impl MutationRoot {
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let q_id = question_id.parse::<i64>()?;
+        let question = fetch_question_for_admin(&mut tr, question_id.parse::<i64>()?)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_QUESTION"))?;
+        if question.used.is_some() {
+            return Err(AppError::BadRequest("question already used".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "QUESTION_ALREADY_USED")));
+        }
+        let today = crate::schedule::today(&mut tr)
+            .await
+            .log_error_msg(|| "error loading schedule date")
+            .extend()?;
+        if date <= crate::schedule::last_live_day(today) {
+            return Err(AppError::BadRequest(
+                "date must be after tomorrow, earlier days may be live".into(),
+            )
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "DATE_NOT_IN_FUTURE")));
+        }
+        check_question_not_live(&mut tr, question.id, today).await?;
+        // free up the day, and the question if it was already scheduled elsewhere
         sqlx::query(
-            r##"update pin.pinions set deleted = true where user_id = $1 and question_id = $2"##,
+            r##"
+            update pin.question_schedule
+                set deleted = true, modified = now()
+                where deleted is false and group_id is null and (day = $1 or question_id = $2)
+            "##,
         )
-        .bind(user.id)
-        .bind(q_id)
+        .bind(date)
+        .bind(question.id)
         .execute(&mut *tr)
         .await
         .map_err(AppError::from)
-        .extend_err(|_e, ex| {
-            ex.set("key", "DATABASE_ERROR");
-        })?;
-        let pinion: Pinion = sqlx::query_as(
+        .log_error_msg(|| "error clearing scheduled day")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let scheduled = ScheduledQuestion::create(&mut tr, None, date, question.id, false)
+            .await
+            .log_error_msg(|| "error scheduling question")
+            .extend()?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        refill_schedule(pool).await;
+        Ok(scheduled)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    /// Clear a day after tomorrow on the schedule. Question days are filled back in by priority
+    async fn unschedule_question(&self, ctx: &Context<'_>, date: NaiveDate) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let today = crate::schedule::today(&mut tr)
+            .await
+            .log_error_msg(|| "error loading schedule date")
+            .extend()?;
+        if date <= crate::schedule::last_live_day(today) {
+            return Err(AppError::BadRequest(
+                "date must be after tomorrow, earlier days may be live".into(),
+            )
+            .extend()
+            .extend_with(|_e, ex| ex.set("key", "DATE_NOT_IN_FUTURE")));
+        }
+        let cleared = sqlx::query(
             r##"
-            insert into pin.pinions
-                (user_id, question_id, multi_selection)
-                values ($1, $2, $3)
-                returning *
-        "##,
+            update pin.question_schedule
+                set deleted = true, modified = now()
+                where deleted is false and group_id is null and day = $1
+            "##,
         )
-        .bind(user.id)
-        .bind(q_id)
-        .bind(multi_selection_id.parse::<i64>()?)
-        .fetch_one(&mut *tr)
+        .bind(date)
+        .execute(&mut *tr)
         .await
         .map_err(AppError::from)
-        .extend_err(|e, ex| {
-            if let Some((_code, _constraint)) = e.unique_constraint_error() {
-                tracing::info!("{} submitted multiple pinions in one day", &user.handle);
-                ex.set("key", "MULTIPLE_DAILY_RESPONSES");
-            } else {
-                tracing::error!("error saving pinion {:?}", e);
-                ex.set("key", "DATABASE_ERROR");
-            }
-        })?;
+        .log_error_msg(|| "error clearing scheduled day")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(pinion)
+        refill_schedule(pool).await;
+        Ok(cleared.rows_affected() > 0)
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Submit a comment for a pinion
-    async fn comment(
-        &self,
-        ctx: &Context<'_>,
-        pinion_id: String,
-        content: String,
-    ) -> FieldResult<bool> {
+    /// Create a group with the current user as its admin
+    async fn create_group(&self, ctx: &Context<'_>, name: String) -> FieldResult<Group> {
         let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
+        let name =
+            Group::validate_name(&name).extend_err(|_e, ex| ex.set("key", "INVALID_GROUP_NAME"))?;
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let pinion_id = pinion_id.parse::<i64>()?;
-        sqlx::query(
+        let group: Group = sqlx::query_as(
             r##"
-            insert into pin.comments
-                (pinion_id, user_id, content)
-                values ($1, $2, $3)
+            insert into pin.groups
+                (name, creating_user_id)
+                values ($1, $2)
+                returning *
             "##,
         )
-        .bind(pinion_id)
+        .bind(name)
         .bind(user.id)
-        .bind(content)
-        .execute(&mut *tr)
+        .fetch_one(&mut *tr)
         .await
         .map_err(AppError::from)
-        .extend_err(|_e, ex| {
-            ex.set("key", "DATABASE_ERROR");
-        })?;
+        .log_error_msg(|| "error creating group")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        GroupAssociation::create(&mut tr, group.id, user.id, GroupRole::ADMIN)
+            .await
+            .log_error_msg(|| "error adding group creator")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(true)
+        Ok(group)
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Accept a friend request
-    async fn accept_fiend(
+    /// Add a question to a group's queue, requires a role that can manage the group.
+    /// Members get the group's questions by priority as `group.questionOfDay`.
+    /// Takes the same kinds of questions as `createQuestion`
+    #[allow(clippy::too_many_arguments)]
+    async fn submit_group_question(
         &self,
         ctx: &Context<'_>,
-        relationship_id: String,
-    ) -> FieldResult<Friend> {
+        group_id: String,
+        prompt: String,
+        #[graphql(default)] options: Vec<String>,
+        kind: Option<String>,
+        scale_min: Option<i64>,
+        scale_max: Option<i64>,
+        scale_step: Option<i64>,
+    ) -> FieldResult<Question> {
+        let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
+        let new = new_question(prompt, options, kind, scale_min, scale_max, scale_step)
+            .and_then(NewQuestion::validate)
+            .extend()?;
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let relationship_id = relationship_id.parse::<i64>()?;
-        let f: Friend = sqlx::query_as(
-            r#"
-            update pin.friends
-                set accepted = now()
+        let (group, _, role) = fetch_group_membership(&mut tr, group_id.parse::<i64>()?, user.id)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_GROUP"))?;
+        if !role.can_manage {
+            return Err(group_permission_denied());
+        }
+        let question = Question::create(&mut tr, &new, None, Some(group.id))
+            .await
+            .log_error_msg(|| "error creating group question")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        refill_schedule(pool).await;
+        Ok(question)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Rename a group, requires a role that can manage the group
+    async fn rename_group(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+        name: String,
+    ) -> FieldResult<Group> {
+        let user = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let name =
+            Group::validate_name(&name).extend_err(|_e, ex| ex.set("key", "INVALID_GROUP_NAME"))?;
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let (group, _, role) = fetch_group_membership(&mut tr, group_id.parse::<i64>()?, user.id)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_GROUP"))?;
+        if !role.can_manage {
+            return Err(group_permission_denied());
+        }
+        let group: Group = sqlx::query_as(
+            r##"
+            update pin.groups
+                set name = $2, modified = now()
                 where id = $1
-                and deleted is false
-            "#,
+                returning *
+            "##,
         )
-        .bind(relationship_id)
+        .bind(group.id)
+        .bind(name)
         .fetch_one(&mut *tr)
         .await
         .map_err(AppError::from)
-        .extend()?;
+        .log_error_msg(|| "error renaming group")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(f)
+        Ok(group)
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Request a friendship
-    async fn request_friend(&self, ctx: &Context<'_>, phone_number: String) -> FieldResult<Friend> {
+    /// Invite another user to a group, requires a role that can invite.
+    /// The other user joins with `joinGroup`
+    async fn invite_to_group(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+        user_id: String,
+    ) -> FieldResult<GroupInvite> {
         let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
+        let invitee_id = user_id.parse::<i64>()?;
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let other_user = User::fetch_user_by_number(&mut tr, &phone_number)
+        let (group, _, role) = fetch_group_membership(&mut tr, group_id.parse::<i64>()?, user.id)
             .await
-            .log_error_msg(|| "error querying other user by phone number")
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_GROUP"))?;
+        if !role.can_invite {
+            return Err(group_permission_denied());
+        }
+        let invitee = User::fetch_user(&mut tr, invitee_id)
+            .await
+            .log_error_msg(|| "unable to load invited user")
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_USER"))?;
+        if GroupAssociation::fetch(&mut tr, group.id, invitee.id)
+            .await
+            .log_error_msg(|| "error fetching group membership")
             .extend()?
-            .ok_or_else(|| AppError::from("unable to find other user by phone number"))
-            .extend()?;
-        let f: Friend = sqlx::query_as(
-            r#"
-            insert into pin.friends
-                (requestor_id, acceptor_id)
-                values ($1, $2)
+            .is_some()
+        {
+            return Err(
+                AppError::BadRequest("user is already a group member".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "ALREADY_GROUP_MEMBER")),
+            );
+        }
+        let invite: GroupInvite = sqlx::query_as(
+            r##"
+            insert into pin.group_invites
+                (group_id, inviter_id, invitee_id)
+                values ($1, $2, $3)
                 returning *
-            "#,
+            "##,
         )
+        .bind(group.id)
         .bind(user.id)
-        .bind(other_user.id)
+        .bind(invitee.id)
         .fetch_one(&mut *tr)
         .await
         .map_err(AppError::from)
         .extend_err(|e, ex| {
             if let Some((_code, _constraint)) = e.unique_constraint_error() {
-                tracing::info!(
-                    "{}:{} friend relationship already exists",
-                    &user.handle,
-                    &other_user.handle
-                );
-                ex.set("key", "DUPLICATE_FRIEND_REQUEST");
+                tracing::info!("{} already invited to group {}", &invitee.handle, group.id);
+                ex.set("key", "DUPLICATE_GROUP_INVITE");
             } else {
-                tracing::error!("error creating friend relationship {:?}", e);
+                tracing::error!("error creating group invite {:?}", e);
                 ex.set("key", "DATABASE_ERROR");
             }
         })?;
@@ -872,65 +2856,75 @@ impl MutationRoot {
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(f)
+        Ok(invite)
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Request a friendship by other user's ID
-    async fn request_friend_id(&self, ctx: &Context<'_>, user_id: String) -> FieldResult<Friend> {
+    /// Accept a pending invite and join the group as a member
+    async fn join_group(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+    ) -> FieldResult<GroupAssociation> {
         let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
-        let other_user_id = user_id.parse::<i64>()?;
+        let group_id = group_id.parse::<i64>()?;
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let other_user = User::fetch_user(&mut tr, other_user_id)
+        let no_invite = || {
+            AppError::BadRequest("no pending invite to group".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "NO_GROUP_INVITE"))
+        };
+        let group = Group::fetch_for_update(&mut tr, group_id)
             .await
-            .log_error_msg(|| "unable to load other user")?;
-        let f: Friend = sqlx::query_as(
-            r#"
-            insert into pin.friends
-                (requestor_id, acceptor_id)
-                values ($1, $2)
-                returning *
-            "#,
+            .log_error_msg(|| "error fetching group")
+            .extend()?
+            .ok_or_else(no_invite)?;
+        let invite = GroupInvite::fetch_pending(&mut tr, group.id, user.id)
+            .await
+            .log_error_msg(|| "error fetching group invite")
+            .extend()?
+            .ok_or_else(no_invite)?;
+        sqlx::query(
+            r##"
+            update pin.group_invites
+                set accepted = now(), modified = now()
+                where id = $1
+            "##,
         )
-        .bind(user.id)
-        .bind(other_user_id)
-        .fetch_one(&mut *tr)
+        .bind(invite.id)
+        .execute(&mut *tr)
         .await
         .map_err(AppError::from)
-        .extend_err(|e, ex| {
-            if let Some((_code, _constraint)) = e.unique_constraint_error() {
-                tracing::info!(
-                    "{}:{} friend relationship already exists",
-                    &user.handle,
-                    &other_user.handle
-                );
-                ex.set("key", "DUPLICATE_FRIEND_REQUEST");
-            } else {
-                tracing::error!("error creating friend relationship {:?}", e);
-                ex.set("key", "DATABASE_ERROR");
-            }
-        })?;
+        .log_error_msg(|| "error accepting group invite")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let membership = GroupAssociation::create(&mut tr, group.id, user.id, GroupRole::MEMBER)
+            .await
+            .extend_err(|e, ex| {
+                if let Some((_code, _constraint)) = e.unique_constraint_error() {
+                    ex.set("key", "ALREADY_GROUP_MEMBER");
+                } else {
+                    tracing::error!("error joining group {:?}", e);
+                    ex.set("key", "DATABASE_ERROR");
+                }
+            })?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(f)
+        Ok(membership)
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Terminate a friendship
-    async fn delete_fiend(
-        &self,
-        ctx: &Context<'_>,
-        relationship_id: String,
-    ) -> FieldResult<Friend> {
+    /// Leave a group. The last admin can only leave once no other members
+    /// remain, the group is deleted when its last member leaves
+    async fn leave_group(&self, ctx: &Context<'_>, group_id: String) -> FieldResult<bool> {
         let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
         let mut tr = pool
@@ -939,74 +2933,130 @@ impl MutationRoot {
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let relationship_id = relationship_id.parse::<i64>()?;
-        let f: Friend = sqlx::query_as(
-            r#"
-            select * from pin.friends where id = $1 and deleted is false
-            "#,
-        )
-        .bind(relationship_id)
-        .fetch_one(&mut *tr)
-        .await
-        .map_err(AppError::from)
-        .extend()?;
-        if user.id != f.requestor_id && user.id != f.acceptor_id {
-            return Err(AppError::BadRequest("User not related to friendship".into()).extend());
+        let (group, membership, _) =
+            fetch_group_membership(&mut tr, group_id.parse::<i64>()?, user.id)
+                .await
+                .extend_err(|_e, ex| ex.set("key", "UNKNOWN_GROUP"))?;
+        let admins = GroupAssociation::count_with_role(&mut tr, group.id, GroupRole::ADMIN)
+            .await
+            .log_error_msg(|| "error counting group admins")
+            .extend()?;
+        let members = GroupAssociation::count_with_role(&mut tr, group.id, GroupRole::MEMBER)
+            .await
+            .log_error_msg(|| "error counting group members")
+            .extend()?;
+        if membership.role == GroupRole::ADMIN && admins <= 1 && members > 0 {
+            return Err(last_group_admin());
+        }
+        GroupAssociation::delete(&mut tr, membership.id)
+            .await
+            .log_error_msg(|| "error leaving group")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        if admins + members <= 1 {
+            sqlx::query(
+                r##"
+                update pin.groups
+                    set deleted = true, modified = now()
+                    where id = $1
+                "##,
+            )
+            .bind(group.id)
+            .execute(&mut *tr)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error deleting empty group")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+            sqlx::query(
+                r##"
+                update pin.group_invites
+                    set deleted = true, modified = now()
+                    where group_id = $1 and deleted is false and accepted is null
+                "##,
+            )
+            .bind(group.id)
+            .execute(&mut *tr)
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error deleting invites of empty group")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         }
-
-        let f: Friend = sqlx::query_as(
-            r#"
-            update pin.friends
-                set deleted = true
-                where id = $1
-                and deleted is false
-            "#,
-        )
-        .bind(relationship_id)
-        .fetch_one(&mut *tr)
-        .await
-        .map_err(AppError::from)
-        .extend()?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(f)
+        Ok(true)
     }
+
     #[graphql(guard = "LoginGuard::new()")]
-    /// Check if phone numbers are associated with signed up users
-    async fn check_phones(
+    /// Remove another member from a group, requires a role that can manage the group
+    async fn remove_member(
         &self,
         ctx: &Context<'_>,
-        phone_numbers: Vec<String>,
-    ) -> FieldResult<Vec<PhoneCheck>> {
+        group_id: String,
+        user_id: String,
+    ) -> FieldResult<bool> {
+        let user = ctx.data_unchecked::<User>();
         let pool = ctx.data_unchecked::<PgPool>();
+        let member_id = user_id.parse::<i64>()?;
+        if member_id == user.id {
+            return Err(
+                AppError::BadRequest("use leaveGroup to leave a group".into())
+                    .extend()
+                    .extend_with(|_e, ex| ex.set("key", "CANNOT_REMOVE_SELF")),
+            );
+        }
         let mut tr = pool
             .begin()
             .await
             .map_err(AppError::from)
             .log_error_msg(|| "error starting transaction")
             .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
-        let checks: Vec<PhoneCheck> = sqlx::query_as(
-            r#"
-            select in_num as number, p.number is not null as signed_up
-            from unnest($1) as in_num
-            left outer join pin.phones p on p.number = in_num and p.deleted is false;
-            "#,
-        )
-        .bind(&phone_numbers)
-        .fetch_all(&mut *tr)
-        .await
-        .map_err(AppError::from)
-        .log_error_msg(|| "failed querying for phones")
-        .extend()?;
+        let (group, _, role) = fetch_group_membership(&mut tr, group_id.parse::<i64>()?, user.id)
+            .await
+            .extend_err(|_e, ex| ex.set("key", "UNKNOWN_GROUP"))?;
+        if !role.can_manage {
+            return Err(group_permission_denied());
+        }
+        let member = GroupAssociation::fetch(&mut tr, group.id, member_id)
+            .await
+            .log_error_msg(|| "error fetching group member")
+            .extend()?
+            .ok_or_else(|| AppError::BadRequest(format!("user {member_id} is not a group member")))
+            .extend_err(|_e, ex| ex.set("key", "NOT_GROUP_MEMBER"))?;
+        GroupAssociation::delete(&mut tr, member.id)
+            .await
+            .log_error_msg(|| "error removing group member")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
         tr.commit()
             .await
             .map_err(AppError::from)
             .log_error()
             .extend()?;
-        Ok(checks)
+        Ok(true)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Make a group member an admin, requires a role that can manage the group
+    async fn promote_member(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+        user_id: String,
+    ) -> FieldResult<GroupAssociation> {
+        set_group_member_role(ctx, &group_id, &user_id, GroupRole::ADMIN).await
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Make a group admin a regular member, requires a role that can manage
+    /// the group. Admins can demote themselves as long as another admin remains
+    async fn demote_member(
+        &self,
+        ctx: &Context<'_>,
+        group_id: String,
+        user_id: String,
+    ) -> FieldResult<GroupAssociation> {
+        set_group_member_role(ctx, &group_id, &user_id, GroupRole::MEMBER).await
     }
 }
 
@@ -1022,14 +3072,135 @@ impl QueryRoot {
     }
 
     #[graphql(guard = "LoginGuard::new()")]
-    /// Retrieve the question of the day
+    /// List the current user's active sessions, most recently used first
+    async fn sessions(&self, ctx: &Context<'_>) -> FieldResult<Vec<Session>> {
+        let u = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let sessions = Session::fetch_active_for_user(&mut tr, u.id)
+            .await
+            .log_error_msg(|| "failed querying for sessions")
+            .extend()?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(sessions)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Users the current user has blocked, most recent first
+    async fn blocked_users(&self, ctx: &Context<'_>) -> FieldResult<Vec<Block>> {
+        let u = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let blocks = Block::fetch_for_user(pool, u.id)
+            .await
+            .log_error_msg(|| "failed querying for blocked users")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(blocks)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// The key to HMAC contacts' phone numbers with for `checkPhoneHashes`
+    async fn contact_hash_key(&self) -> &str {
+        &CONFIG.contact_hash_key
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Users the current user might want to add, best matches first. Ranked by
+    /// mutual friends, uploaded contacts (see `checkPhones`) and shared groups
+    async fn friend_suggestions(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] limit: i64,
+    ) -> FieldResult<Vec<FriendSuggestion>> {
+        let u = ctx.data_unchecked::<User>();
+        let pool = ctx.data_unchecked::<PgPool>();
+        let suggestions = FriendSuggestion::fetch_for_user(pool, u.id, limit.clamp(1, 100))
+            .await
+            .log_error_msg(|| "failed querying for friend suggestions")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(suggestions)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    /// List unused questions in priority order, questions scheduled for
+    /// a specific day by an admin aren't included
+    async fn upcoming_questions(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 50)] limit: i64,
+    ) -> FieldResult<Vec<Question>> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let questions: Vec<Question> = sqlx::query_as(
+            r##"
+            select * from pin.questions
+                where deleted is false
+                    and used is null
+                    and group_id is null
+                    and id not in (
+                        select question_id from pin.question_schedule
+                        where deleted is false and auto is false
+                    )
+                order by priority asc, created asc
+                limit $1
+            "##,
+        )
+        .bind(limit.clamp(1, 500))
+        .fetch_all(pool)
+        .await
+        .map_err(AppError::from)
+        .log_error_msg(|| "failed querying for upcoming questions")
+        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        Ok(questions)
+    }
+
+    #[graphql(guard = "AdminGuard::new()")]
+    /// View the question calendar, starting today unless `from` is given
+    async fn question_schedule(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<NaiveDate>,
+        #[graphql(default = 14)] days: i64,
+    ) -> FieldResult<Vec<ScheduledQuestion>> {
+        let pool = ctx.data_unchecked::<PgPool>();
+        let mut tr = pool
+            .begin()
+            .await
+            .map_err(AppError::from)
+            .log_error_msg(|| "error starting transaction")
+            .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))?;
+        let from = match from {
+            Some(from) => from,
+            None => crate::schedule::today(&mut tr)
+                .await
+                .log_error_msg(|| "error loading schedule date")
+                .extend()?,
+        };
+        let to = from + chrono::Duration::days(days.clamp(1, 366) - 1);
+        let schedule = ScheduledQuestion::fetch_range(&mut tr, None, from, to)
+            .await
+            .log_error_msg(|| "failed querying for question schedule")
+            .extend()?;
+        tr.commit()
+            .await
+            .map_err(AppError::from)
+            .log_error()
+            .extend()?;
+        Ok(schedule)
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Retrieve the question of the day for the current user's local day
     async fn question_of_day(&self, ctx: &Context<'_>) -> FieldResult<Question> {
-        let r = ctx
-            .data_unchecked::<AppLoader>()
-            .load_one(QuestionOfDay {})
-            .await?
-            .unwrap();
-        Ok(r)
+        let u = ctx.data_unchecked::<User>();
+        u.local_question_of_day(ctx).await
     }
 
     #[graphql(guard = "LoginGuard::new()")]
@@ -1068,6 +3239,7 @@ impl QueryRoot {
                     or
                     pr.name ilike $2
                 )
+                and not pin.is_blocked($1, u.id)
             "#,
         )
         .bind(u.id)
@@ -1086,4 +3258,130 @@ impl QueryRoot {
     }
 }
 
-pub type Schema = async_graphql::Schema<QueryRoot, MutationRoot, EmptySubscription>;
+pub struct SubscriptionRoot;
+
+/// Ends a subscription once the session that started it is logged out or revoked,
+/// checked before each item is sent
+fn while_session_active<T>(
+    ctx: &Context<'_>,
+    stream: impl Stream<Item = T>,
+) -> impl Stream<Item = T> {
+    let pool = ctx.data_unchecked::<PgPool>().clone();
+    let session = ctx.data_unchecked::<CurrentSession>().clone();
+    stream.take_while(move |_| {
+        let pool = pool.clone();
+        let session = session.clone();
+        async move {
+            session
+                .is_active(&pool)
+                .await
+                .log_error_msg(|| "error checking subscription session")
+                .unwrap_or(false)
+        }
+    })
+}
+
+#[Subscription]
+impl SubscriptionRoot {
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Emits a question's summary right away and again whenever its tallies change
+    async fn question_summary_updated(
+        &self,
+        ctx: &Context<'_>,
+        question_id: String,
+    ) -> FieldResult<impl Stream<Item = FieldResult<QuestionSummary>>> {
+        let question_id = question_id.parse::<i64>()?;
+        let question = ctx
+            .data_unchecked::<AppLoader>()
+            .load_one(QuestionId(question_id))
+            .await?;
+        if let Some(question) = question {
+            question.check_visible(ctx).await?;
+        }
+        let pool = ctx.data_unchecked::<PgPool>().clone();
+        let updates = BroadcastStream::new(ctx.data_unchecked::<Events>().summaries.subscribe())
+            .filter_map(move |id| {
+                futures::future::ready(match id {
+                    Ok(id) if id == question_id => Some(()),
+                    // fell behind and missed some updates, the next summary catches up
+                    Err(BroadcastStreamRecvError::Lagged(_)) => Some(()),
+                    _ => None,
+                })
+            });
+        let stream = futures::stream::once(futures::future::ready(()))
+            .chain(updates)
+            .then(move |_| {
+                let pool = pool.clone();
+                async move {
+                    question_summary(question_id, &pool)
+                        .await
+                        .log_error_msg(|| "error querying summary")
+                        .extend_err(|_e, ex| ex.set("key", "DATABASE_ERROR"))
+                }
+            });
+        Ok(while_session_active(ctx, stream))
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Emits comments as they're added to a pinion of the current user or one of their
+    /// friends, except comments by users blocked by or blocking the current user
+    async fn comment_added(
+        &self,
+        ctx: &Context<'_>,
+        pinion_id: String,
+    ) -> FieldResult<impl Stream<Item = Comment>> {
+        let user_id = ctx.data_unchecked::<User>().id;
+        let pool = ctx.data_unchecked::<PgPool>().clone();
+        let pinion_id = pinion_id.parse::<i64>()?;
+        if Pinion::fetch_visible(&pool, pinion_id, user_id)
+            .await
+            .extend()?
+            .is_none()
+        {
+            return Err(AppError::BadRequest("unknown pinion".into())
+                .extend()
+                .extend_with(|_e, ex| ex.set("key", "UNKNOWN_PINION")));
+        }
+        let stream = BroadcastStream::new(ctx.data_unchecked::<Events>().comments.subscribe())
+            .filter_map(move |c| {
+                let pool = pool.clone();
+                async move {
+                    let c = c.ok().filter(|c| c.pinion_id == pinion_id)?;
+                    let c = Comment::fetch(&pool, c.id)
+                        .await
+                        .log_error_msg(|| format!("error loading comment {}", c.id))
+                        .ok()
+                        .flatten()?;
+                    let blocked = Block::exists_between(&pool, user_id, c.user_id)
+                        .await
+                        .log_error_msg(|| "error checking comment author blocks")
+                        .ok()?;
+                    (!blocked).then_some(c)
+                }
+            });
+        Ok(while_session_active(ctx, stream))
+    }
+
+    #[graphql(guard = "LoginGuard::new()")]
+    /// Emits friend requests sent to the current user
+    async fn friend_request_received(&self, ctx: &Context<'_>) -> impl Stream<Item = Friend> {
+        let user_id = ctx.data_unchecked::<User>().id;
+        let pool = ctx.data_unchecked::<PgPool>().clone();
+        let stream =
+            BroadcastStream::new(ctx.data_unchecked::<Events>().friend_requests.subscribe())
+                .filter_map(move |f| {
+                    let pool = pool.clone();
+                    async move {
+                        let f = f.ok().filter(|f| f.acceptor_id == user_id)?;
+                        Friend::fetch(&pool, f.id)
+                            .await
+                            .log_error_msg(|| format!("error loading friend request {}", f.id))
+                            .ok()
+                            .flatten()
+                    }
+                });
+        while_session_active(ctx, stream)
+    }
+}
+
+pub type Schema = async_graphql::Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
@@ -0,0 +1,169 @@
+/*!
+SMS delivery backends
+*/
+use crate::{AppError, Result, CONFIG};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+/// Something that can deliver a text message to a phone number
+#[async_trait::async_trait]
+pub trait SmsSender: Send + Sync {
+    async fn send(&self, to: &str, body: &str) -> Result<()>;
+}
+
+/// The configured sender, shared through the graphql context
+pub type Sms = Arc<dyn SmsSender>;
+
+/// Build the sender selected by `SMS_BACKEND`
+pub fn from_config() -> Result<Sms> {
+    let sms: Sms = match CONFIG.sms_backend.as_str() {
+        "twilio" => Arc::new(TwilioSender::default()),
+        "file" => Arc::new(FileSender::new(&CONFIG.sms_file_path)),
+        "http" => {
+            let url = CONFIG
+                .sms_http_url
+                .clone()
+                .ok_or_else(|| AppError::from("SMS_HTTP_URL is required for the http backend"))?;
+            Arc::new(HttpSender::new(url, CONFIG.sms_http_authorization.clone()))
+        }
+        other => return Err(AppError::from(format!("unknown SMS_BACKEND: {other}"))),
+    };
+    Ok(sms)
+}
+
+/// Sends messages through twilio's messaging service api
+#[derive(Default)]
+pub struct TwilioSender {
+    client: reqwest::Client,
+}
+
+#[async_trait::async_trait]
+impl SmsSender for TwilioSender {
+    async fn send(&self, to: &str, body: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct Msg<'a> {
+            #[serde(rename = "To")]
+            to: &'a str,
+            #[serde(rename = "MessagingServiceSid")]
+            msg_sid: &'a str,
+            #[serde(rename = "Body")]
+            body: &'a str,
+        }
+        let msg = Msg {
+            to,
+            msg_sid: &CONFIG.twilio_messaging_service_sid,
+            body,
+        };
+        let url = format!(
+            "https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json",
+            CONFIG.twilio_account
+        );
+        let _resp: serde_json::Value = self
+            .client
+            .post(&url)
+            .basic_auth(&CONFIG.twilio_sid, Some(&CONFIG.twilio_secret))
+            .form(&msg)
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("{:?}", e);
+                e
+            })?
+            .json()
+            .await
+            .map_err(|e| {
+                tracing::error!("{:?}", e);
+                e
+            })?;
+        Ok(())
+    }
+}
+
+/// Appends messages as json lines to a local file instead of sending them.
+/// Used for local dev and CI so the signup and login flows work offline.
+pub struct FileSender {
+    path: PathBuf,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl FileSender {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsSender for FileSender {
+    async fn send(&self, to: &str, body: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            to: &'a str,
+            body: &'a str,
+            sent: String,
+        }
+        let mut line = serde_json::to_string(&Line {
+            to,
+            body,
+            sent: chrono::Utc::now().to_rfc3339(),
+        })?;
+        line.push('\n');
+        tracing::info!(to = %to, path = ?self.path, len = body.len(), "writing sms to file");
+
+        let _guard = self.lock.lock().await;
+        let mut f = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| AppError::from(format!("error opening sms file {:?}: {e}", self.path)))?;
+        f.write_all(line.as_bytes())
+            .await
+            .map_err(|e| AppError::from(format!("error writing sms file {:?}: {e}", self.path)))?;
+        Ok(())
+    }
+}
+
+/// Posts `{"to": .., "body": ..}` json to a configurable endpoint
+pub struct HttpSender {
+    client: reqwest::Client,
+    url: String,
+    authorization: Option<String>,
+}
+
+impl HttpSender {
+    pub fn new(url: String, authorization: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            authorization,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsSender for HttpSender {
+    async fn send(&self, to: &str, body: &str) -> Result<()> {
+        #[derive(Serialize)]
+        struct Msg<'a> {
+            to: &'a str,
+            body: &'a str,
+        }
+        let mut req = self.client.post(&self.url).json(&Msg { to, body });
+        if let Some(auth) = &self.authorization {
+            req = req.header("authorization", auth);
+        }
+        req.send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| {
+                tracing::error!("{:?}", e);
+                e
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+/*!
+Response tallies
+
+A trigger on `pin.pinions` sends a `pinion_changes` notification naming the question
+and option whenever an answer is added, changed, or removed, and the `events` listener
+recounts just those options. Recounting (rather than applying +1/-1) keeps tallies
+correct when several servers are listening to the same notifications.
+*/
+use crate::error::LogError;
+use crate::loaders::QOD_QUERY;
+use crate::models::{forget_question_summary, Question};
+use crate::{AppError, Result};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Payload of a `pinion_changes` notification
+#[derive(Debug, Clone, Deserialize)]
+pub struct PinionChange {
+    pub question_id: i64,
+    /// Missing for answers to questions that aren't multiple choice
+    pub multi_selection: Option<i64>,
+}
+
+/// Recount one option's responses
+pub async fn recount_option(pool: &PgPool, question_id: i64, multi_selection: i64) -> Result<()> {
+    sqlx::query(
+        r##"
+        insert into pin.question_multi_option_tallies
+            (question_id, multi_selection, count)
+            select $1, $2, count(*)
+            from pin.pinions
+            where question_id = $1 and multi_selection = $2 and deleted is false
+        on conflict (question_id, multi_selection) where deleted is false
+        do update set
+            count = excluded.count,
+            modified = now()
+        "##,
+    )
+    .bind(question_id)
+    .bind(multi_selection)
+    .execute(pool)
+    .await
+    .map_err(AppError::from)?;
+    Ok(())
+}
+
+/// Recount every option of a question
+pub async fn recount_question(pool: &PgPool, question_id: i64) -> Result<()> {
+    sqlx::query(
+        r##"
+        insert into pin.question_multi_option_tallies
+            (question_id, multi_selection, count)
+            select o.question_id, o.id, count(p.id)
+            from pin.question_multi_options o
+                left outer join pin.pinions p
+                    on p.multi_selection = o.id and p.deleted is false
+            where o.question_id = $1 and o.deleted is false
+            group by o.question_id, o.id
+        on conflict (question_id, multi_selection) where deleted is false
+        do update set
+            count = excluded.count,
+            modified = now()
+        "##,
+    )
+    .bind(question_id)
+    .execute(pool)
+    .await
+    .map_err(AppError::from)?;
+    forget_question_summary(question_id).await;
+    Ok(())
+}
+
+/// Recount every question with pinions added or changed in the last `seconds`,
+/// returns the number of questions recounted
+pub async fn recount_active(pool: &PgPool, seconds: i64) -> Result<usize> {
+    let ids: Vec<(i64,)> = sqlx::query_as(
+        r##"
+        select distinct question_id from pin.pinions
+            where modified > now() - $1 * interval '1 second'
+        "##,
+    )
+    .bind(seconds)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)?;
+    for (id,) in &ids {
+        recount_question(pool, *id).await?;
+    }
+    Ok(ids.len())
+}
+
+/// Recount every question that has been used or answered, returns
+/// the number of questions recounted
+pub async fn backfill(pool: &PgPool) -> Result<usize> {
+    let ids: Vec<(i64,)> = sqlx::query_as(
+        r##"
+        select id from pin.questions where used is not null and deleted is false
+        union
+        select distinct question_id from pin.pinions
+        "##,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::from)?;
+    for (i, (id,)) in ids.iter().enumerate() {
+        recount_question(pool, *id).await?;
+        if (i + 1) % 100 == 0 {
+            tracing::info!("tally: backfilled {} of {} questions", i + 1, ids.len());
+        }
+    }
+    Ok(ids.len())
+}
+
+/// How far back to recount on startup, when there's no telling how long
+/// notifications went unheard
+pub const RECONCILE_LOOKBACK_SECONDS: i64 = 60 * 10;
+
+/// Catch up on anything missed while not listening for the last `seconds`
+pub async fn reconcile(pool: &PgPool, seconds: i64) -> Result<()> {
+    let question: Option<Question> = sqlx::query_as(QOD_QUERY)
+        .fetch_optional(pool)
+        .await
+        .map_err(AppError::from)?;
+    if let Some(q) = question {
+        recount_question(pool, q.id).await?;
+    }
+    recount_active(pool, seconds).await?;
+    Ok(())
+}
+
+/// Periodically recount questions with recent activity, a safety net for
+/// notifications that never arrived. Each pass looks back over two intervals
+/// so passes overlap. Only runs when `TALLY_RECONCILE_SECONDS` is set
+pub async fn maintain(pool: PgPool, interval: i64) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval as u64)).await;
+        recount_active(&pool, 2 * interval)
+            .await
+            .map(|n| tracing::info!("tally: reconciled {} active questions", n))
+            .log_error_msg(|| "tally: error reconciling active questions")
+            .ok();
+    }
+}
+
+/// Recount the option named in a `pinion_changes` notification. Other kinds
+/// of answers are counted when their summary is loaded
+pub async fn handle_change(pool: &PgPool, change: &PinionChange) -> Result<()> {
+    if let Some(multi_selection) = change.multi_selection {
+        recount_option(pool, change.question_id, multi_selection).await?;
+    }
+    forget_question_summary(change.question_id).await;
+    Ok(())
+}